@@ -1,42 +1,532 @@
-use chrono::Local;
+use brotli::CompressorWriter;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use clappers::Clappers;
 use comrak::{markdown_to_html, ComrakOptions};
 use cwd::cwd;
 use die::die;
-use lazy_static::lazy_static;
-use minifier::{css, js};
-use minify::html;
-use placeholder::render;
-use regex::Regex;
-use std::collections::HashMap;
-use std::env;
-use std::fs::{read, read_to_string, remove_file, write};
-use tiny_http::{Response, Server, StatusCode};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use glob::{glob, Pattern};
+use html5ever::serialize::{serialize as serialize_html, SerializeOpts, TraversalScope};
+use html5ever::tendril::TendrilSink;
+use html5ever::tree_builder::{ElementFlags, NodeOrText, TreeBuilderOpts, TreeSink};
+use html5ever::{namespace_url, ns, parse_document, Attribute, LocalName, ParseOpts, QualName};
+use image::imageops::FilterType;
+use lazy_static::{initialize, lazy_static};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+use notify::{recommended_watcher, Config, PollWatcher, RecursiveMode, Watcher};
+use regex::{Captures, Match, Regex};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sssg::{minify_css, minify_html, minify_html_conservative, minify_js, sanitise_url, Error};
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env::{set_current_dir, var_os};
+use std::fs::{
+    copy, create_dir_all, metadata, read, read_dir, read_to_string, remove_file, write, File,
+    OpenOptions,
+};
+use std::io::{copy as copy_io, sink, stdin, stdout, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::slice::from_ref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{available_parallelism, scope, spawn};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
 use toml::{from_str, Value};
 use walkdir::WalkDir;
 
+type Minifier = fn(&str) -> Result<String, String>;
+
+type Lists = HashMap<String, Vec<HashMap<String, String>>>;
+
+// Pages in a directory, keyed by their canonical path so the current page can be marked.
+type Siblings = Vec<(PathBuf, HashMap<String, String>)>;
+
+struct Output {
+    source: String,
+    filename: String,
+    contents: String,
+    rendered_size: usize,
+}
+
+// Files and globs read while rendering one page, and the warnings it raised, so a cached
+// page can be checked against what it actually read and its warnings repeated.
+#[derive(Default)]
+struct PageReads {
+    files: BTreeSet<String>,
+    globs: BTreeSet<String>,
+    warnings: Vec<String>,
+}
+
+// An <img> with a single local src, as found by both image passes.
+struct LocalImage<'a> {
+    tag: Match<'a>,
+    src_attribute: Range<usize>,
+    src: &'a str,
+    path: &'a str,
+    filename: PathBuf,
+}
+
+struct Tag {
+    slug: String,
+    name: String,
+    items: Vec<HashMap<String, String>>,
+}
+
+struct DataPage {
+    source: String,
+    template: String,
+    filename: String,
+    values: HashMap<String, String>,
+    lists: Lists,
+}
+
+struct BuildCache {
+    filename: PathBuf,
+    global: String,
+    pages: String,
+    entries: serde_json::Map<String, serde_json::Value>,
+}
+
+struct Site {
+    config: Value,
+    data: Value,
+    vars: HashMap<String, String>,
+    siblings: HashMap<PathBuf, Siblings>,
+}
+
+const REBUILD_PATH: &str = "/__sssg/rebuild";
+
+const STATS_PATH: &str = "/__sssg/stats";
+
+const DEFAULT_HEALTH_PATH: &str = "/healthz";
+
+const DEFAULT_AUTOINDEX_LIMIT: usize = 1000;
+
+const DEFAULT_MARKDOWN_TEMPLATE: &str =
+    "<!DOCTYPE html><html><head></head><body>{body}</body></html>";
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+const DEFAULT_MIN_COMPRESS_SIZE: usize = 1024;
+
+const MISSING_TEMPLATE: &str = "Missing template";
+
+const PROFILE_PHASES: [&str; 6] = [
+    "discovery",
+    "parsing",
+    "markdown",
+    "templates",
+    "minification",
+    "writing",
+];
+
+// Written by --build-manifest to the site root: a JSON object of output paths,
+// relative to htdocs, to the lowercase hex SHA-256 of the bytes written.
+const BUILD_MANIFEST: &str = ".sssg-manifest.json";
+
+// Written by --search-index to htdocs and removed again by --clean.
+const SEARCH_INDEX: &str = "search-index.json";
+
+const DEFAULT_CACHE_DIR: &str = ".sssg-cache";
+
+// Stand-ins for braces in substituted values while a template is still being rendered.
+const ESCAPED_OPEN_BRACE: &str = "\u{e001}";
+const ESCAPED_CLOSE_BRACE: &str = "\u{e002}";
+
+const DEFAULT_EXTERNAL_CONCURRENCY: usize = 8;
+
+const DEFAULT_EXTERNAL_TIMEOUT: u64 = 10;
+
+// Bump whenever the layout of the build cache changes so old caches are ignored.
+const CACHE_VERSION: u64 = 2;
+
+const HELP_OPTIONS: &[(&str, &str)] = &[
+    (
+        "-b, --build [GLOB...]",
+        "render sources (only those matching GLOB, if given)",
+    ),
+    ("-c, --clean", "remove generated files"),
+    (
+        "-l, --list",
+        "print each source and the outputs it generates",
+    ),
+    ("-s, --serve", "serve htdocs over HTTP"),
+    (
+        "-w, --watch",
+        "rebuild sources as they change (with --serve)",
+    ),
+    ("-v, --version", "print the version"),
+    ("--new PATH", "create a starter page at PATH (.html.sssg)"),
+    (
+        "--template-name NAME",
+        "template for --new (default page.html)",
+    ),
+    ("--force", "let --new overwrite an existing page"),
+    (
+        "--print-config PATH",
+        "print the merged config a page is rendered with",
+    ),
+    ("--stdin", "render a page read from stdin to stdout"),
+    (
+        "--root DIR",
+        "run from DIR instead of the current directory",
+    ),
+    ("--config FILE", "site config file (default sssg.toml)"),
+    (
+        "--var KEY=VALUE",
+        "set {KEY} for every page, over [plaintext] (repeatable)",
+    ),
+    ("--verbose", "log each step to stderr"),
+    ("--strict", "fail the build if any warning was raised"),
+    (
+        "--strict-sections",
+        "warn about non-string values in [plaintext] and [markdown]",
+    ),
+    (
+        "--fail-on-missing-template",
+        "fail instead of skipping pages with no template",
+    ),
+    (
+        "--since DURATION|TIME",
+        "only render sources modified since then",
+    ),
+    (
+        "--threads-build N",
+        "render with N threads (default: CPU count)",
+    ),
+    ("--max-open N", "open at most N files at once (default 64)"),
+    ("--markdown-template FILE", "template for .md.sssg pages"),
+    (
+        "--minify-html LEVEL",
+        "none, conservative or aggressive (default)",
+    ),
+    (
+        "--minify-report",
+        "print the size saved by minifying each output",
+    ),
+    (
+        "--line-endings lf|crlf",
+        "line endings for written outputs (default lf)",
+    ),
+    ("--trailing-newline", "end every output with a newline"),
+    (
+        "--inline-images-below BYTES",
+        "inline smaller images as data: URLs",
+    ),
+    (
+        "--responsive-images",
+        "write resized image variants with srcset",
+    ),
+    ("--search-index", "write htdocs/search-index.json"),
+    (
+        "--build-manifest",
+        "write the .sssg-manifest.json output digest",
+    ),
+    ("--validate-html", "warn about malformed generated HTML"),
+    ("--check-links", "warn about links to missing files"),
+    (
+        "--check-external",
+        "warn about unreachable external links (needs curl)",
+    ),
+    (
+        "--external-concurrency N",
+        "concurrent external checks (default 8)",
+    ),
+    (
+        "--external-timeout SECS",
+        "timeout per external check (default 10)",
+    ),
+    ("--precompress", "write .gz copies of outputs"),
+    ("--profile", "print time spent in each build phase"),
+    (
+        "--cache-dir DIR",
+        "build cache directory (default .sssg-cache)",
+    ),
+    ("--no-cache", "ignore cached outputs"),
+    ("--pre-build COMMAND", "run COMMAND before building"),
+    ("--post-build COMMAND", "run COMMAND after building"),
+    ("--host HOST", "address to serve on (default 0.0.0.0)"),
+    ("--port PORT", "port to serve on (default 1337)"),
+    ("--threads N", "request worker threads (default: CPU count)"),
+    ("--timeout SECS", "time limit for each request (default 30)"),
+    (
+        "--allowed-hosts HOSTS",
+        "comma separated Host headers to accept",
+    ),
+    (
+        "--allow-dotfiles",
+        "serve paths with segments starting with '.'",
+    ),
+    ("--autoindex", "list directories that have no index.html"),
+    (
+        "--autoindex-limit N",
+        "entries per directory listing (default 1000)",
+    ),
+    (
+        "--health-path PATH",
+        "health check path (default /healthz, empty disables)",
+    ),
+    ("--log-file FILE", "append access log lines to FILE"),
+    ("--log-format FORMAT", "human (default), clf or json"),
+    ("--tee", "also print access log lines when using --log-file"),
+    ("--max-body BYTES", "reject larger request bodies with 413"),
+    (
+        "--min-compress-size BYTES",
+        "smallest response to compress (default 1024)",
+    ),
+    ("--mount PREFIX=DIR", "serve DIR under PREFIX (repeatable)"),
+    (
+        "--negotiate-language",
+        "serve page.<lang>.html from Accept-Language",
+    ),
+    ("--poll MS", "watch by polling every MS milliseconds"),
+    (
+        "--rebuild-token TOKEN",
+        "token required by POST /__sssg/rebuild",
+    ),
+    (
+        "--root-file FILE",
+        "file to serve for / instead of index.html",
+    ),
+];
+
+// Where each kind of template variable comes from, in the order later sources win.
+const TEMPLATE_VARIABLES: &[(&str, &str)] = &[
+    (
+        "{name}",
+        "the page's [plaintext], then --var, then the page's [markdown]",
+    ),
+    (
+        "{config.name}",
+        "the site config's [config], then the page's [config]",
+    ),
+    (
+        "{data.file.name}",
+        "files in data/, which --var never overrides",
+    ),
+];
+
+// Field names are stable; new fields may be added but existing ones won't change.
+const JSON_LOG_FIELDS: &[(&str, &str)] = &[
+    ("duration_ms", "time taken to respond, in milliseconds"),
+    ("method", "request method"),
+    ("remote_addr", "client address and port"),
+    ("request_id", "id also sent as the X-Request-Id header"),
+    ("size", "response body size in bytes"),
+    ("status", "response status code"),
+    ("timestamp", "RFC 3339 time the request finished, in UTC"),
+    ("url", "sanitised request path"),
+];
+
+const AMP_RUNTIME: &str = "https://cdn.ampproject.org/v0.js";
+
+const AMP_BOILERPLATE: &str = "<style amp-boilerplate>body{-webkit-animation:-amp-start 8s steps(1,end) 0s 1 normal both;-moz-animation:-amp-start 8s steps(1,end) 0s 1 normal both;-ms-animation:-amp-start 8s steps(1,end) 0s 1 normal both;animation:-amp-start 8s steps(1,end) 0s 1 normal both}@-webkit-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-moz-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-ms-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-o-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}</style><noscript><style amp-boilerplate>body{-webkit-animation:none;-moz-animation:none;-ms-animation:none;animation:none}</style></noscript>";
+
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONFIG: i32 = 3;
+const EXIT_IO: i32 = 4;
+const EXIT_TEMPLATE: i32 = 5;
+
+const DEFAULT_MIME_TYPES: &[(&str, &str)] = &[
+    ("css", "text/css; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("gif", "image/gif"),
+    ("htm", "text/html; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("ico", "image/x-icon"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("ogg", "audio/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "application/xml"),
+    ("zip", "application/zip"),
+];
+
+static LOG_SINK: Mutex<()> = Mutex::new(());
+static NEXT_REQUEST_ID: AtomicUsize = AtomicUsize::new(1);
+
 lazy_static! {
-    static ref SANITISE_URL: Regex = Regex::new("[.]{2}").unwrap();
-    static ref COMRAK_OPTIONS: ComrakOptions = {
-        let mut options = ComrakOptions::default();
-        options.extension.header_ids = Some(String::from(""));
-        options.render.unsafe_ = true;
-        options
+    static ref CLAPPERS: Clappers = Clappers::build()
+        .set_flags(vec![
+            "allow-dotfiles",
+            "autoindex",
+            "b|build",
+            "build-manifest",
+            "check-external",
+            "check-links",
+            "c|clean",
+            "fail-on-missing-template",
+            "force",
+            "precompress",
+            "responsive-images",
+            "negotiate-language",
+            "no-cache",
+            "l|list",
+            "minify-report",
+            "profile",
+            "search-index",
+            "s|serve",
+            "stdin",
+            "strict",
+            "strict-sections",
+            "tee",
+            "trailing-newline",
+            "validate-html",
+            "verbose",
+            "v|version",
+            "w|watch",
+        ])
+        .set_singles(vec![
+            "allowed-hosts",
+            "autoindex-limit",
+            "cache-dir",
+            "config",
+            "external-concurrency",
+            "external-timeout",
+            "host",
+            "inline-images-below",
+            "line-endings",
+            "log-file",
+            "log-format",
+            "markdown-template",
+            "max-body",
+            "max-open",
+            "min-compress-size",
+            "minify-html",
+            "new",
+            "poll",
+            "port",
+            "post-build",
+            "pre-build",
+            "print-config",
+            "rebuild-token",
+            "root",
+            "root-file",
+            "since",
+            "template-name",
+            "threads",
+            "threads-build",
+            "timeout",
+        ])
+        .set_multiples(vec!["health-path", "mount", "var"])
+        .parse();
+    static ref HTML_TAG: Regex = Regex::new("(?i)<html([^>]*)>").unwrap();
+    static ref HEAD_TAG: Regex = Regex::new(r"(?i)<head(\s[^>]*)?>").unwrap();
+    static ref HEAD_CLOSE_TAG: Regex = Regex::new(r"(?i)</head\s*>").unwrap();
+    static ref LINK_TAG: Regex = Regex::new(r"(?i)<link\s[^>]*>").unwrap();
+    static ref STYLESHEET_REL: Regex =
+        Regex::new(r#"(?i)\srel\s*=\s*["']?stylesheet["']?"#).unwrap();
+    static ref META_CHARSET: Regex = Regex::new(r"(?i)<meta\s[^>]*charset\s*=").unwrap();
+    static ref ROOT_RELATIVE_URL: Regex =
+        Regex::new(r#"(?i)(\s(?:href|src)\s*=\s*["']?)(/[^"'\s>]*)"#).unwrap();
+    static ref LANG_ATTR: Regex = Regex::new(r"(?i)\slang\s*=").unwrap();
+    static ref LESS_VARIABLE_DEFINITION: Regex = Regex::new(r"(?s)^@([\w-]+)\s*:(.*)$").unwrap();
+    static ref LESS_VARIABLE_REFERENCE: Regex =
+        Regex::new(r#""(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|url\([^)]*\)|@\{([\w-]+)\}|@([\w-]+)"#)
+            .unwrap();
+    static ref DATA_PLACEHOLDER: Regex = Regex::new(r"\{data((?:\.[\w-]+)+)\}").unwrap();
+    static ref ROW_PLACEHOLDER: Regex = Regex::new(r"\{([\w-]+)\}").unwrap();
+    static ref TEMPLATE_BLOCK: Regex =
+        Regex::new(r"\{#(each|if) ([\w.-]+)\}|\{/(each|if)\}").unwrap();
+    static ref HEADING: Regex = Regex::new(
+        r##"(?s)<h([1-6])[^>]*><a href="#[^"]*" aria-hidden="true" class="anchor" id="([^"]*)"></a>(.*?)</h[1-6]>"##
+    )
+    .unwrap();
+    static ref HTML_TAG_ANY: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref SHORTCODE: Regex = Regex::new(r"\{\{%\s*([\w-]+)\s*%\}\}").unwrap();
+    static ref SHORTCODE_PLACEHOLDER: Regex =
+        Regex::new("<p>\u{e001}([0-9]+)\u{e001}</p>|\u{e001}([0-9]+)\u{e001}").unwrap();
+    static ref MAX_OPEN: usize = match CLAPPERS.get_single("max-open").as_str() {
+        "" => 64,
+        n => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| die!("Max open files '{}' must be a positive integer", n; EXIT_USAGE)),
     };
+    static ref OPEN_FILES: Mutex<usize> = Mutex::new(0);
+    static ref OPEN_FILE_CLOSED: Condvar = Condvar::new();
+    static ref INCLUDE_RAW: Regex = Regex::new(r"\{\{include-raw\s+([^}\s]+)\s*\}\}").unwrap();
+    static ref RENDER_PAGE: Regex = Regex::new(r"\{\{render-page\s+([^}\s]+)\s*\}\}").unwrap();
+    static ref FOOTNOTE_REFERENCE: Regex =
+        Regex::new(r#"<a href="\#fn-([^"]+)" id="fnref-[^"]+" data-footnote-ref>"#).unwrap();
+    static ref FOOTNOTE_BACKREFERENCE: Regex =
+        Regex::new(r#"<a href="\#fnref-([^"]+)" class="footnote-backref"[^>]*>↩</a>"#).unwrap();
+    static ref FOOTNOTES_SECTION: Regex =
+        Regex::new(r#"<section class="footnotes"[^>]*>"#).unwrap();
+    static ref IMG_TAG: Regex = Regex::new(r"(?i)<img\s[^>]*>").unwrap();
+    static ref IMG_SRCSET: Regex = Regex::new(r"(?i)\ssrcset\s*=").unwrap();
+    static ref IMG_SRC_ANY: Regex =
+        Regex::new(r#"(?i)\ssrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^"'\s>]+))"#).unwrap();
+    static ref RESIZING_IMAGES: Mutex<()> = Mutex::new(());
+    static ref WARNINGS: Mutex<Vec<String>> = Mutex::new(vec![]);
+    static ref PROFILE: Mutex<HashMap<&'static str, Duration>> = Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    static PAGE_READS: RefCell<Option<PageReads>> = const { RefCell::new(None) };
 }
 
 fn main() {
-    let clappers = Clappers::build()
-        .set_flags(vec!["b|build", "c|clean", "s|serve", "v|version"])
-        .set_singles(vec!["host", "port"])
-        .parse();
+    let clappers = &*CLAPPERS;
+
+    let root = clappers.get_single("root");
+
+    if !root.is_empty() {
+        set_current_dir(&root)
+            .unwrap_or_else(|err| die!("Error changing to root '{}' ({})", root, err; EXIT_USAGE));
+    }
 
-    if clappers.get_flag("build") {
-        generate_files()
+    if clappers.get_flag("serve") {
+        if clappers.get_flag("build") {
+            generate_files(clappers);
+            log_event("Built all files");
+        }
+
+        scope(|s| {
+            if clappers.get_flag("watch") {
+                s.spawn(|| watch_sources(clappers));
+            }
+
+            serve_htdocs(clappers)
+        })
+    } else if !clappers.get_single("new").is_empty() {
+        new_page(clappers)
+    } else if !clappers.get_single("print-config").is_empty() {
+        print_config(clappers)
+    } else if clappers.get_flag("list") {
+        list_outputs(clappers)
+    } else if clappers.get_flag("stdin") {
+        render_stdin(clappers)
+    } else if clappers.get_flag("build") {
+        generate_files(clappers)
     } else if clappers.get_flag("clean") {
-        delete_generated_files()
-    } else if clappers.get_flag("serve") {
-        serve_htdocs(&clappers)
+        delete_generated_files(clappers)
     } else if clappers.get_flag("version") {
         println!("{}", env!("CARGO_PKG_VERSION"))
     } else {
@@ -44,168 +534,5472 @@ fn main() {
     }
 }
 
-fn generate_files() {
+fn generate_files(clappers: &Clappers) {
     let htdocs = format!("{}/htdocs", cwd());
+    let mut site = load_site(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+    initialize(&MAX_OPEN);
+
+    let filenames = profile("discovery", || collect_sources(&htdocs));
+
+    site.siblings =
+        collect_siblings(&filenames).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    run_hook(
+        "pre_build",
+        clappers,
+        &site.config,
+        &htdocs,
+        filenames.len(),
+    );
+
+    let mut outputs = vec![];
+
+    let since = match clappers.get_single("since").as_str() {
+        "" => None,
+        s => Some(parse_since(s).unwrap_or_else(
+            || die!("Value of '--since' must be a duration (e.g. 10m) or a timestamp"; EXIT_USAGE),
+        )),
+    };
+
+    let targets = clappers
+        .get_leftovers()
+        .iter()
+        .map(|t| {
+            Pattern::new(t)
+                .unwrap_or_else(|err| die!("Invalid build target '{}' ({})", t, err; EXIT_USAGE))
+        })
+        .collect::<Vec<Pattern>>();
+
+    let is_target = |f: &str| {
+        let path = Path::new(f).strip_prefix(&htdocs).unwrap_or(Path::new(f));
+        targets.is_empty() || targets.iter().any(|t| t.matches_path(path))
+    };
+
+    let changed = filenames
+        .iter()
+        .filter(|f| is_target(f))
+        .filter(|f| since.is_none_or(|since| modified_since(f, since)))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if !targets.is_empty() && !filenames.iter().any(|f| is_target(f)) {
+        warn(String::from("no sources matched the build targets"));
+    }
+
+    let cache = open_build_cache(clappers, &htdocs);
+    let mut entries = cache.entries.clone();
+    let mut uncached = vec![];
+
+    entries.retain(|f, _| filenames.contains(f));
+
+    let keys = changed
+        .iter()
+        .filter_map(|f| cache_key(&cache, f).map(|k| (f.to_string(), k)))
+        .collect::<HashMap<String, String>>();
+
+    for filename in &changed {
+        match keys
+            .get(filename)
+            .and_then(|k| cached_outputs(&cache, filename, k))
+        {
+            Some((o, warnings)) => {
+                log_verbose(clappers, &format!("Using cached output for '{}'", filename));
+                warnings.into_iter().for_each(warn);
+                outputs.extend(o);
+            }
+            None => uncached.push(filename.to_string()),
+        }
+    }
+
+    for (filename, (rendered, reads)) in uncached
+        .iter()
+        .zip(render_files(&uncached, clappers, &site))
+    {
+        entries.remove(filename);
+
+        match rendered {
+            Ok(o) => {
+                if let Some(key) = keys.get(filename) {
+                    entries.insert(filename.to_string(), cache_entry(key, &o, &reads));
+                }
+
+                outputs.extend(o);
+            }
+            Err(err @ Error::MissingTemplate(_))
+                if !clappers.get_flag("fail-on-missing-template") =>
+            {
+                warn(format!("skipped page: {}", err))
+            }
+            Err(err) => die!("{}", err; exit_code(&err)),
+        }
+    }
+
+    outputs.extend(
+        render_tag_pages(&filenames, clappers, &site)
+            .unwrap_or_else(|err| die!("{}", err; exit_code(&err))),
+    );
+
+    outputs.extend(
+        render_data_pages(clappers, &site).unwrap_or_else(|err| die!("{}", err; exit_code(&err))),
+    );
+
+    if let Some(since) = since {
+        println!(
+            "Rendered {} file(s), skipped {} not modified since {}",
+            changed.len(),
+            filenames.len() - changed.len(),
+            since.with_timezone(&Local).naive_local()
+        );
+    }
+
+    if clappers.get_flag("search-index") {
+        let index = search_index(&outputs);
+
+        outputs.push(Output {
+            source: String::from("search index"),
+            filename: format!("{htdocs}/{SEARCH_INDEX}"),
+            rendered_size: index.len(),
+            contents: index,
+        });
+    }
+
+    check_output_conflicts(&outputs).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+    copy_files(&outputs, clappers, &site.config)
+        .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    let links = match clappers.get_flag("check-links") || clappers.get_flag("check-external") {
+        false => vec![],
+        true => outputs
+            .iter()
+            .filter(|o| o.filename.ends_with(".html"))
+            .map(|o| (o.filename.clone(), html_links(&o.contents)))
+            .collect(),
+    };
+
+    if clappers.get_flag("minify-report") {
+        print_minify_report(&outputs);
+    }
+
+    let written = outputs
+        .iter()
+        .map(|o| o.filename.to_string())
+        .collect::<Vec<String>>();
 
-    let filenames = WalkDir::new(htdocs)
+    let mut generated = written.clone();
+
+    generated.extend(
+        copy_targets(&site.config)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, d)| d.display().to_string()),
+    );
+
+    profile("writing", || {
+        for output in outputs {
+            write_output(output, clappers, &site.config)
+                .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+        }
+    });
+
+    if clappers.get_flag("build-manifest") {
+        write_build_manifest(&written, &htdocs, changed.len() < filenames.len())
+            .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+    }
+
+    if clappers.get_flag("check-links") {
+        check_links(&links, &site.config);
+    }
+
+    if clappers.get_flag("check-external") {
+        check_external_links(&links, clappers, &site.config)
+            .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+    }
+
+    if clappers.get_flag("profile") {
+        print_profile();
+    }
+
+    let warnings = take_warnings();
+
+    if clappers.get_flag("strict") && !warnings.is_empty() {
+        die!(
+            "Build failed with {} warning(s) in strict mode",
+            warnings.len()
+        );
+    }
+
+    save_build_cache(&cache, entries, &generated)
+        .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    run_hook(
+        "post_build",
+        clappers,
+        &site.config,
+        &htdocs,
+        filenames.len(),
+    );
+}
+
+fn open_build_cache(clappers: &Clappers, htdocs: &str) -> BuildCache {
+    let directory = match clappers.get_single("cache-dir").as_str() {
+        "" => String::from(DEFAULT_CACHE_DIR),
+        d => d.to_string(),
+    };
+
+    let filename = PathBuf::from(cwd()).join(directory).join("cache.json");
+
+    let previous = read_to_string(&filename)
+        .ok()
+        .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok())
+        .filter(|c| c["version"].as_u64() == Some(CACHE_VERSION))
+        .unwrap_or_default();
+
+    let generated = previous["generated"]
+        .as_array()
         .into_iter()
-        .filter(|f| f.is_ok())
-        .map(|f| f.unwrap().path().display().to_string())
-        .filter(|f| f.ends_with(".sssg"))
+        .flatten()
+        .filter_map(|g| g.as_str().map(String::from))
+        .collect::<HashSet<String>>();
+
+    BuildCache {
+        filename,
+        global: global_digest(clappers),
+        pages: pages_digest(htdocs, &generated),
+        entries: match clappers.get_flag("no-cache") {
+            true => serde_json::Map::new(),
+            false => previous["entries"].as_object().cloned().unwrap_or_default(),
+        },
+    }
+}
+
+// The shared inputs of every page, plus the command line and version, so a change to any
+// of them invalidates every cached output. Anything else a page reads is recorded in its
+// own cache entry.
+fn global_digest(clappers: &Clappers) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(CACHE_VERSION.to_le_bytes());
+    hasher.update(env!("CARGO_PKG_VERSION"));
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-cache" | "--profile" | "--verbose" => continue,
+            "--cache-dir" => {
+                args.next();
+                continue;
+            }
+            _ => (),
+        }
+
+        hasher.update(arg);
+        hasher.update([0]);
+    }
+
+    hasher.update(read(site_config_filename(clappers)).unwrap_or_default());
+
+    for directory in ["assets", "data", "templates"] {
+        let mut filenames = WalkDir::new(format!("{}/{directory}", cwd()))
+            .into_iter()
+            .filter_map(|f| f.ok())
+            .filter(|f| f.file_type().is_file())
+            .map(|f| f.path().display().to_string())
+            .collect::<Vec<String>>();
+
+        filenames.sort();
+
+        for filename in filenames {
+            hasher.update(&filename);
+            hasher.update(read(&filename).unwrap_or_default());
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+// Pages can pull in other pages (collections, siblings, tags, includes) and
+// arbitrary files under htdocs, so any such change invalidates every page.
+// Page sources are hashed by content; other files by size and mtime, which can
+// only cause a needless rebuild, never a stale skip.
+fn pages_digest(htdocs: &str, generated: &HashSet<String>) -> String {
+    let mut hasher = Sha256::new();
+
+    let mut filenames = WalkDir::new(htdocs)
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .filter(|f| f.file_type().is_file())
+        .map(|f| f.path().display().to_string())
+        .filter(|f| !generated.contains(f.strip_suffix(".gz").unwrap_or(f)))
+        .filter(|f| !f.ends_with(".sssg") || f.ends_with(".html.sssg") || f.ends_with(".md.sssg"))
         .collect::<Vec<String>>();
 
+    filenames.sort();
+
     for filename in filenames {
-        let contents = read_to_string(&filename)
-            .unwrap_or_else(|err| die!("Error reading '{}' ({})", filename, err));
+        hasher.update(&filename);
 
-        let output = match filename.rsplit('.').skip(1).take(1).next() {
-            None => die!(
-                "Filename '{}' not in the form <name>.(css|html|js).sssg",
-                filename
-            ),
-            Some(filetype) => match filetype {
-                "css" => css::minify(&contents).map_err(|e| e.to_string()),
-                "html" => generate_html(&contents),
-                "js" => Ok(js::minify(&contents)),
-                _ => die!(
-                    "Filename '{}' not in the form <name>.(css|html|js).sssg",
-                    filename
-                ),
-            },
-        };
+        if filename.ends_with(".sssg") {
+            hasher.update(read(&filename).unwrap_or_default());
+        } else if let Ok(m) = metadata(&filename) {
+            hasher.update(m.len().to_le_bytes());
 
-        match output {
-            Err(err) => die!("Error generating content for '{}' ({})", filename, err),
-            Ok(o) => write(&filename.strip_suffix(".sssg").unwrap(), &o)
-                .unwrap_or_else(|err| die!("Error writing to '{}' ({})", filename, err)),
+            if let Ok(modified) = m.modified() {
+                hasher.update(format!("{:?}", modified));
+            }
         }
     }
+
+    format!("{:x}", hasher.finalize())
 }
 
-fn generate_html(contents: &str) -> Result<String, String> {
-    let document = from_str(contents).map_err(|err| format!("TOML parse error: {}", err))?;
-    let config = get_section("config", &document);
-    let mut plaintext = get_section("plaintext", &document);
+fn cache_key(cache: &BuildCache, filename: &str) -> Option<String> {
+    let contents = read(filename).ok()?;
+    let mut hasher = Sha256::new();
+
+    hasher.update(&cache.global);
 
-    for (name, markdown) in get_section("markdown", &document) {
-        plaintext.insert(name, markdown_to_html(&markdown, &COMRAK_OPTIONS));
+    if matches!(filename.rsplit('.').nth(1), Some("html" | "md")) {
+        hasher.update(&cache.pages);
     }
 
-    let template = config
-        .get("template")
-        .ok_or("Template file not defined in 'config' section")
-        .map(|t| format!("{}/templates/{t}", cwd()))?;
+    hasher.update(contents);
 
-    let template_contents = read_to_string(&template)
-        .unwrap_or_else(|err| die!("Error reading template file '{}' ({})", template, err));
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+fn file_digest(filename: &str) -> String {
+    read(filename)
+        .map(|c| format!("{:x}", Sha256::digest(c)))
+        .unwrap_or_default()
+}
+
+// Matches are hashed with their mtimes since globbed markdown can be ordered by them.
+fn glob_digest(pattern: &str) -> String {
+    let mut hasher = Sha256::new();
 
-    let output = render(&template_contents, &plaintext)
-        .map_err(|err| format!("Template variable '{}' is missing its value", err))?;
+    for filename in glob(pattern).into_iter().flatten().filter_map(|f| f.ok()) {
+        hasher.update(filename.display().to_string());
 
-    Ok(html::minify(&output))
+        if let Ok(modified) = metadata(&filename).and_then(|m| m.modified()) {
+            hasher.update(format!("{:?}", modified));
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
 }
 
-fn get_section(name: &str, document: &Value) -> HashMap<String, String> {
-    let mut values = HashMap::new();
+fn cached_outputs(
+    cache: &BuildCache,
+    filename: &str,
+    key: &str,
+) -> Option<(Vec<Output>, Vec<String>)> {
+    let entry = cache.entries.get(filename).filter(|e| e["key"] == key)?;
 
-    match document.get(name) {
-        None => values,
-        Some(c) => match c.as_table() {
-            None => values,
-            Some(t) => {
-                for v in t.iter() {
-                    values.insert(v.0.to_string(), v.1.as_str().unwrap_or("").to_string());
-                }
+    let unchanged = |name: &str, digest: fn(&str) -> String| {
+        entry[name]
+            .as_object()
+            .is_some_and(|m| m.iter().all(|(f, d)| d.as_str() == Some(&digest(f))))
+    };
 
-                values
-            }
-        },
+    if !unchanged("files", file_digest) || !unchanged("globs", glob_digest) {
+        return None;
     }
+
+    let outputs = entry["outputs"]
+        .as_array()?
+        .iter()
+        .map(|o| {
+            Some(Output {
+                source: filename.to_string(),
+                filename: o["filename"].as_str()?.to_string(),
+                contents: o["contents"].as_str()?.to_string(),
+                rendered_size: o["rendered_size"].as_u64()? as usize,
+            })
+        })
+        .collect::<Option<Vec<Output>>>()?;
+
+    let warnings = entry["warnings"]
+        .as_array()?
+        .iter()
+        .filter_map(|w| w.as_str().map(String::from))
+        .collect();
+
+    Some((outputs, warnings))
 }
 
-fn serve_htdocs(clappers: &Clappers) {
-    let host = match clappers.get_single("host").as_str() {
-        "" => "0.0.0.0".to_string(),
-        h => h.to_string(),
+fn cache_entry(key: &str, outputs: &[Output], reads: &PageReads) -> serde_json::Value {
+    let digests = |names: &BTreeSet<String>, digest: fn(&str) -> String| {
+        names
+            .iter()
+            .map(|n| (n.to_string(), json!(digest(n))))
+            .collect::<serde_json::Map<_, _>>()
     };
 
-    let port = match clappers.get_single("port").as_str() {
-        "" => "1337".to_string(),
-        p => p.to_string(),
+    json!({
+        "files": digests(&reads.files, file_digest),
+        "globs": digests(&reads.globs, glob_digest),
+        "key": key,
+        "outputs": outputs
+            .iter()
+            .map(|o| json!({
+                "contents": o.contents,
+                "filename": o.filename,
+                "rendered_size": o.rendered_size,
+            }))
+            .collect::<Vec<_>>(),
+        "warnings": reads.warnings,
+    })
+}
+
+fn save_build_cache(
+    cache: &BuildCache,
+    entries: serde_json::Map<String, serde_json::Value>,
+    generated: &[String],
+) -> Result<(), Error> {
+    let mut generated = generated.to_vec();
+
+    generated.extend(entries.values().flat_map(|e| {
+        e["outputs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|o| o["filename"].as_str().map(String::from))
+    }));
+
+    generated.sort();
+    generated.dedup();
+
+    let contents = json!({
+        "entries": entries,
+        "generated": generated,
+        "version": CACHE_VERSION,
+    });
+
+    if let Some(directory) = cache.filename.parent() {
+        create_dir_all(directory).map_err(|err| {
+            Error::Io(format!(
+                "Error creating '{}' ({})",
+                directory.display(),
+                err
+            ))
+        })?;
+    }
+
+    write(&cache.filename, contents.to_string()).map_err(|err| {
+        Error::Io(format!(
+            "Error writing to '{}' ({})",
+            cache.filename.display(),
+            err
+        ))
+    })
+}
+
+fn list_outputs(clappers: &Clappers) {
+    let site = load_site_config(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    let filenames = collect_sources(&format!("{}/htdocs", cwd()));
+
+    for filename in &filenames {
+        for output in
+            planned_outputs(filename, &site).unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+        {
+            println!("{} -> {}", filename, output);
+        }
+    }
+
+    for tag in
+        collect_tags(&filenames, &site).unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+    {
+        println!("tag '{}' -> {}", tag.name, tag_filename(&tag.slug, &site));
+    }
+
+    for page in collect_data_pages(
+        &site,
+        &load_data().unwrap_or_else(|err| die!("{}", err; exit_code(&err))),
+    )
+    .unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+    {
+        println!("{} -> {}", page.source, page.filename);
+    }
+}
+
+fn planned_outputs(filename: &str, site: &Value) -> Result<Vec<String>, Error> {
+    let mut config = config_section(site);
+    config.extend(page_config(filename));
+
+    let page_count = match config.get("collection") {
+        None => 1,
+        Some(pattern) => {
+            let directory = Path::new(filename)
+                .parent()
+                .unwrap_or_else(|| Path::new("."));
+            let items = collection_filenames(pattern, filename, directory)?.len();
+
+            items.div_ceil(items_per_page(&config, items)?).max(1)
+        }
     };
 
-    let server = Server::http(format!("{host}:{port}")).unwrap();
+    let mut outputs = vec![];
 
-    for request in server.incoming_requests() {
-        let url = SANITISE_URL.replace_all(request.url(), "_");
-        let error_url = url.to_string();
+    for target in page_targets(filename) {
+        for page in 1..=page_count {
+            outputs.push(target_filename(&page_filename(filename, page)?, &target));
+        }
+    }
 
-        let (message, status_code) = if url.ends_with(".sssg") {
-            (String::from("File not found").as_bytes().to_vec(), 404)
-        } else {
-            let filename = if url.ends_with('/') {
-                format!("{}/htdocs{url}index.html", cwd())
-            } else {
-                format!("{}/htdocs{url}", cwd())
-            };
+    Ok(outputs)
+}
 
-            match read(&filename) {
-                Ok(contents) => (contents, 200),
-                Err(err) => (
-                    format!("Error reading file '{}' ({})", filename, err)
-                        .as_bytes()
-                        .to_vec(),
-                    404,
-                ),
-            }
-        };
+fn render_stdin(clappers: &Clappers) {
+    let mut contents = String::new();
 
-        let response = Response::from_data(message).with_status_code(StatusCode(status_code));
+    stdin()
+        .read_to_string(&mut contents)
+        .unwrap_or_else(|err| die!("Error reading stdin ({})", err; EXIT_IO));
 
-        println!(
-            "[{}] {status_code} {} {}",
-            Local::now().naive_local(),
-            request.remote_addr(),
-            &url
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+    let filename = format!("{}/htdocs/stdin.html.sssg", cwd());
+    let site = load_site(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    let mut output = generate_html(&filename, contents, clappers, &site)
+        .and_then(|mut pages| Ok(html_minifier(clappers, &site)?(&pages.remove(0).1)?))
+        .unwrap_or_else(
+            |err| die!("Error generating content for stdin ({})", err; exit_code(&err)),
         );
 
-        if request.respond(response).is_err() {
-            die!("Error sending response for '{}'", error_url)
-        }
+    if clappers.get_flag("trailing-newline") && !output.ends_with('\n') {
+        output.push('\n');
     }
+
+    stdout()
+        .write_all(output.as_bytes())
+        .unwrap_or_else(|err| die!("Error writing to stdout ({})", err; EXIT_IO));
 }
 
-fn delete_generated_files() {
-    let filenames = WalkDir::new(format!("{}/htdocs", cwd()))
-        .into_iter()
-        .filter(|f| f.is_ok())
-        .map(|f| f.unwrap().path().display().to_string())
-        .filter(|f| f.ends_with(".sssg"))
-        .collect::<Vec<String>>();
+fn profile<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    if !CLAPPERS.get_flag("profile") {
+        return f();
+    }
 
-    for filename in filenames {
-        match filename.rsplit('.').skip(1).take(1).next() {
-            None => die!(
-                "Filename '{}' not in the form <name>.(css|html|js).sssg",
-                filename
-            ),
-            Some(filetype) => match filetype {
-                "css" | "html" | "js" => {
-                    let generated_filename = filename.strip_suffix(".sssg").unwrap();
+    let started = Instant::now();
+    let result = f();
 
-                    remove_file(generated_filename).unwrap_or_else(|err| {
-                        die!("Error removing file '{}' ({})", generated_filename, err)
-                    });
-                }
-                _ => die!(
-                    "Filename '{}' not in the form <name>.(css|html|js).sssg",
-                    filename
-                ),
-            },
-        };
+    *PROFILE.lock().unwrap().entry(phase).or_default() += started.elapsed();
+    result
+}
+
+fn print_profile() {
+    let profile = PROFILE.lock().unwrap();
+
+    println!("Build profile (summed across threads):");
+
+    for phase in PROFILE_PHASES {
+        let elapsed = profile.get(phase).copied().unwrap_or_default();
+        println!("  {:<14}{:>12.3}ms", phase, elapsed.as_secs_f64() * 1000.0);
     }
 }
 
-fn show_help() {
-    println!("TODO")
+fn warn(message: String) {
+    eprintln!("Warning: {}", message);
+
+    PAGE_READS.with(|r| {
+        if let Some(reads) = r.borrow_mut().as_mut() {
+            reads.warnings.push(message.clone());
+        }
+    });
+
+    WARNINGS.lock().unwrap().push(message);
+}
+
+fn take_warnings() -> Vec<String> {
+    WARNINGS.lock().unwrap().drain(..).collect()
+}
+
+fn run_hook(name: &str, clappers: &Clappers, site: &Value, htdocs: &str, files: usize) {
+    let command = match clappers.get_single(&name.replace('_', "-")) {
+        c if !c.is_empty() => c,
+        _ => match get_section("hooks", site).remove(name) {
+            Some(c) if !c.is_empty() => c,
+            _ => return,
+        },
+    };
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .env("SSSG_HTDOCS", htdocs)
+        .env("SSSG_FILES", files.to_string())
+        .status()
+        .unwrap_or_else(|err| die!("Error running {} hook '{}' ({})", name, command, err));
+
+    if !status.success() {
+        die!("The {} hook '{}' failed ({})", name, command, status);
+    }
+
+    println!("The {} hook '{}' succeeded ({})", name, command, status);
+}
+
+fn generate_file(filename: &str, clappers: &Clappers, site: &Site) -> Result<(), Error> {
+    for output in render_file(filename, clappers, site)? {
+        write_output(output, clappers, &site.config)?;
+    }
+
+    Ok(())
+}
+
+fn render_files(
+    filenames: &[String],
+    clappers: &Clappers,
+    site: &Site,
+) -> Vec<(Result<Vec<Output>, Error>, PageReads)> {
+    let threads = match clappers.get_single("threads-build").as_str() {
+        "" => available_parallelism().map_or(1, |t| t.get()),
+        t => t.parse::<usize>().ok().filter(|t| *t > 0).unwrap_or_else(
+            || die!("Build threads '{}' must be a positive integer", t; EXIT_USAGE),
+        ),
+    };
+
+    log_verbose(
+        clappers,
+        &format!(
+            "Rendering {} file(s) with {threads} thread(s)",
+            filenames.len()
+        ),
+    );
+
+    parallel_map(filenames, threads, |f| {
+        tracking_reads(|| render_file(f, clappers, site))
+    })
+}
+
+// Maps items over up to `threads` workers, each taking the next unclaimed item, and returns
+// the results in the order of the items.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+
+    let mut results = scope(|s| {
+        let workers = (0..threads.min(items.len()))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut results = vec![];
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        match items.get(i) {
+                            None => break results,
+                            Some(item) => results.push((i, f(item))),
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+fn tracking_reads<T>(render: impl FnOnce() -> T) -> (T, PageReads) {
+    PAGE_READS.with(|r| r.replace(Some(PageReads::default())));
+    let result = render();
+
+    (result, PAGE_READS.with(|r| r.take()).unwrap_or_default())
+}
+
+fn record_read(filename: &Path) {
+    PAGE_READS.with(|r| {
+        if let Some(reads) = r.borrow_mut().as_mut() {
+            reads.files.insert(filename.display().to_string());
+        }
+    });
+}
+
+fn record_glob(pattern: &str) {
+    PAGE_READS.with(|r| {
+        if let Some(reads) = r.borrow_mut().as_mut() {
+            reads.globs.insert(pattern.to_string());
+        }
+    });
+}
+
+fn read_limited<P: AsRef<Path>>(filename: P) -> std::io::Result<String> {
+    record_read(filename.as_ref());
+
+    let mut open = OPEN_FILES.lock().unwrap();
+
+    while *open >= *MAX_OPEN {
+        open = OPEN_FILE_CLOSED.wait(open).unwrap();
+    }
+
+    *open += 1;
+    drop(open);
+
+    let contents = read_to_string(filename);
+
+    *OPEN_FILES.lock().unwrap() -= 1;
+    OPEN_FILE_CLOSED.notify_one();
+
+    contents
+}
+
+fn validate_html(html: &str) -> Vec<String> {
+    let options = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            exact_errors: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let dom = parse_document(RcDom::default(), options).one(html);
+    let mut problems = dom
+        .errors
+        .iter()
+        .filter(|e| !e.ends_with("in insertion mode Initial"))
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>();
+    let mut ids = HashMap::new();
+
+    for (_, id) in element_attributes(&dom)
+        .into_iter()
+        .filter(|(n, _)| n == "id")
+    {
+        *ids.entry(id).or_insert(0) += 1;
+    }
+
+    let mut duplicates = ids
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(id, count)| format!("duplicate id '{}' ({} times)", id, count))
+        .collect::<Vec<_>>();
+
+    duplicates.sort();
+    problems.extend(duplicates);
+
+    problems
+}
+
+fn amp_html(html: &str) -> Result<String, Error> {
+    let mut dom = parse_document(RcDom::default(), ParseOpts::default()).one(html);
+    let mut elements = vec![];
+    let mut nodes = vec![dom.document.clone()];
+
+    while let Some(node) = nodes.pop() {
+        if let NodeData::Element { .. } = node.data {
+            elements.push(node.clone());
+        }
+
+        nodes.extend(node.children.borrow().iter().rev().map(Handle::clone));
+    }
+
+    let mut has_viewport = false;
+    let mut has_runtime = false;
+    let mut has_boilerplate = false;
+
+    for element in elements {
+        let (name, attrs) = match &element.data {
+            NodeData::Element { name, attrs, .. } => (name.local.to_string(), attrs),
+            _ => continue,
+        };
+
+        attrs
+            .borrow_mut()
+            .retain(|a| a.name.local.len() <= 2 || !a.name.local.starts_with("on"));
+
+        let attr = |n: &str| {
+            attrs
+                .borrow()
+                .iter()
+                .find(|a| &*a.name.local == n)
+                .map(|a| a.value.to_string())
+        };
+
+        let remove = match name.as_str() {
+            "html" => {
+                if attr("amp").is_none() {
+                    attrs.borrow_mut().push(html_attribute("amp", ""));
+                }
+
+                false
+            }
+            "meta" => {
+                has_viewport |= attr("name").is_some_and(|n| n.eq_ignore_ascii_case("viewport"));
+                false
+            }
+            "style" => {
+                has_boilerplate |= attr("amp-boilerplate").is_some();
+                false
+            }
+            "script" => {
+                let src = attr("src").unwrap_or_default();
+                has_runtime |= src == AMP_RUNTIME;
+
+                !src.starts_with("https://cdn.ampproject.org/")
+                    && attr("type").as_deref() != Some("application/ld+json")
+            }
+            "link" => attr("rel").is_some_and(|r| r.eq_ignore_ascii_case("stylesheet")),
+            "applet" | "embed" | "frame" | "frameset" | "iframe" | "object" | "param" => true,
+            "img" => {
+                let mut attrs = attrs.borrow().clone();
+
+                if attr("layout").is_none() {
+                    attrs.push(match (attr("width"), attr("height")) {
+                        (Some(_), Some(_)) => html_attribute("layout", "responsive"),
+                        _ => html_attribute("layout", "fill"),
+                    });
+                }
+
+                let image = dom.create_element(
+                    QualName::new(None, ns!(html), LocalName::from("amp-img")),
+                    attrs,
+                    ElementFlags::default(),
+                );
+
+                dom.append_before_sibling(&element, NodeOrText::AppendNode(image));
+                true
+            }
+            _ => false,
+        };
+
+        if remove {
+            dom.remove_from_parent(&element);
+        }
+    }
+
+    let mut output = vec![];
+
+    serialize_html(
+        &mut output,
+        &SerializableHandle::from(dom.document.clone()),
+        SerializeOpts {
+            traversal_scope: TraversalScope::ChildrenOnly(None),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("Error serialising AMP HTML ({})", err))?;
+
+    let output = String::from_utf8_lossy(&output);
+    let mut tags = String::new();
+
+    if !has_viewport {
+        tags.push_str(r#"<meta name="viewport" content="width=device-width">"#);
+    }
+
+    if !has_runtime {
+        tags.push_str(&format!(r#"<script async src="{}"></script>"#, AMP_RUNTIME));
+    }
+
+    if !has_boilerplate {
+        tags.push_str(AMP_BOILERPLATE);
+    }
+
+    Ok(match HEAD_CLOSE_TAG.find(&output) {
+        None => output.to_string(),
+        Some(head) => format!(
+            "{}{tags}{}",
+            &output[..head.start()],
+            &output[head.start()..]
+        ),
+    })
+}
+
+fn html_attribute(name: &str, value: &str) -> Attribute {
+    Attribute {
+        name: QualName::new(None, ns!(), LocalName::from(name)),
+        value: value.into(),
+    }
+}
+
+fn element_attributes(dom: &RcDom) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    let mut nodes = vec![dom.document.clone()];
+
+    while let Some(node) = nodes.pop() {
+        if let NodeData::Element { attrs, .. } = &node.data {
+            for attr in attrs.borrow().iter() {
+                attributes.push((attr.name.local.to_string(), attr.value.to_string()));
+            }
+        }
+
+        nodes.extend(node.children.borrow().iter().rev().map(Handle::clone));
+    }
+
+    attributes
+}
+
+fn search_index(outputs: &[Output]) -> String {
+    let mut entries = outputs
+        .iter()
+        .filter(|o| o.source.ends_with(".sssg") && o.filename.ends_with(".html"))
+        .map(|o| {
+            let dom =
+                parse_document(RcDom::default(), ParseOpts::default()).one(o.contents.as_str());
+            let (title, body) = page_text(&dom);
+
+            json!({
+                "body": body,
+                "title": title,
+                "url": page_url(&o.filename),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| a["url"].as_str().cmp(&b["url"].as_str()));
+
+    serde_json::Value::Array(entries).to_string()
+}
+
+fn page_text(dom: &RcDom) -> (String, String) {
+    let mut title = String::new();
+    let mut body = String::new();
+    let mut nodes = vec![(dom.document.clone(), false)];
+
+    while let Some((node, in_title)) = nodes.pop() {
+        let in_title = match &node.data {
+            NodeData::Element { name, .. } => match &*name.local {
+                "script" | "style" | "template" | "noscript" => continue,
+                "title" => true,
+                "a" | "abbr" | "b" | "code" | "em" | "i" | "kbd" | "mark" | "s" | "small"
+                | "span" | "strong" | "sub" | "sup" | "u" => in_title,
+                _ => {
+                    body.push(' ');
+                    in_title
+                }
+            },
+            NodeData::Text { contents } => {
+                let text = if in_title { &mut title } else { &mut body };
+                text.push_str(&contents.borrow());
+                in_title
+            }
+            _ => in_title,
+        };
+
+        nodes.extend(
+            node.children
+                .borrow()
+                .iter()
+                .rev()
+                .map(|c| (Handle::clone(c), in_title)),
+        );
+    }
+
+    let collapse = |s: &str| s.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    (collapse(&title), collapse(&body))
+}
+
+fn html_links(html: &str) -> Vec<String> {
+    let dom = parse_document(RcDom::default(), ParseOpts::default()).one(html);
+
+    element_attributes(&dom)
+        .into_iter()
+        .filter(|(name, _)| name == "href" || name == "src")
+        .map(|(_, link)| link)
+        .collect()
+}
+
+fn check_links(links: &[(String, Vec<String>)], site: &Value) {
+    let htdocs = PathBuf::from(format!("{}/htdocs", cwd()));
+    let base_path = base_path(&config_section(site));
+
+    for (filename, links) in links {
+        for link in links {
+            if !link_resolves(link, filename, &htdocs, base_path.as_deref()) {
+                warn(format!("broken link '{}' in '{}'", link, filename));
+            }
+        }
+    }
+}
+
+fn check_external_links(
+    links: &[(String, Vec<String>)],
+    clappers: &Clappers,
+    site: &Value,
+) -> Result<(), Error> {
+    let concurrency = match clappers.get_single("external-concurrency").as_str() {
+        "" => DEFAULT_EXTERNAL_CONCURRENCY,
+        c => c.parse::<usize>().ok().filter(|c| *c > 0).unwrap_or_else(
+            || die!("External concurrency '{}' must be a positive integer", c; EXIT_USAGE),
+        ),
+    };
+
+    let timeout = match clappers.get_single("external-timeout").as_str() {
+        "" => DEFAULT_EXTERNAL_TIMEOUT,
+        t => t.parse::<u64>().ok().filter(|t| *t > 0).unwrap_or_else(
+            || die!("External timeout '{}' must be a positive number of seconds", t; EXIT_USAGE),
+        ),
+    };
+
+    let ignore = match site.get("build").and_then(|b| b.get("ignore_external")) {
+        None => vec![],
+        Some(i) => i
+            .as_array()
+            .ok_or(Error::Config(String::from(
+                "Config 'build.ignore_external' must be an array of patterns",
+            )))?
+            .iter()
+            .map(|p| {
+                p.as_str()
+                    .and_then(|p| Pattern::new(p).ok())
+                    .ok_or(Error::Config(format!(
+                        "Invalid 'build.ignore_external' pattern '{}'",
+                        p
+                    )))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let mut pages = BTreeMap::<String, Vec<&str>>::new();
+
+    for (filename, links) in links {
+        for link in links {
+            let url = match link.strip_prefix("//") {
+                Some(l) => format!("https://{}", l),
+                None => link.to_string(),
+            };
+
+            let url = url.split('#').next().unwrap_or_default().to_string();
+
+            if (url.starts_with("http://") || url.starts_with("https://"))
+                && !ignore.iter().any(|p| p.matches(&url))
+            {
+                pages.entry(url).or_default().push(filename);
+            }
+        }
+    }
+
+    let urls = pages.keys().collect::<Vec<_>>();
+
+    // Without curl every request would fail, so rather than report each link as dead, fail
+    // once up front.
+    if !urls.is_empty() {
+        Command::new("curl")
+            .arg("--version")
+            .output()
+            .map_err(|err| {
+                Error::Io(format!(
+                    "Error running curl, which --check-external needs ({})",
+                    err
+                ))
+            })?;
+    }
+
+    log_verbose(
+        clappers,
+        &format!(
+            "Checking {} external link(s) with {concurrency} concurrent request(s)",
+            urls.len()
+        ),
+    );
+
+    let statuses = parallel_map(&urls, concurrency, |url| external_link_status(url, timeout));
+
+    for (url, status) in urls.iter().zip(statuses) {
+        let Err(err) = status else { continue };
+
+        for filename in &pages[*url] {
+            warn(format!(
+                "dead external link '{}' in '{}' ({})",
+                url, filename, err
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn external_link_status(url: &str, timeout: u64) -> Result<(), Error> {
+    let output = Command::new("curl")
+        .args(["--head", "--silent", "--output", "/dev/null"])
+        .args(["--write-out", "%{http_code}", "--max-time"])
+        .arg(timeout.to_string())
+        .arg(url)
+        .output()
+        .map_err(|err| Error::Io(format!("Error running curl ({})", err)))?;
+
+    let status = String::from_utf8_lossy(&output.stdout)
+        .parse::<u16>()
+        .unwrap_or(0);
+
+    match status {
+        0 => Err(format!("connection failed, curl {}", output.status).into()),
+        400.. => Err(format!("HTTP {}", status).into()),
+        _ => Ok(()),
+    }
+}
+
+fn link_resolves(link: &str, filename: &str, htdocs: &Path, base_path: Option<&str>) -> bool {
+    let path = link.split(&['#', '?'][..]).next().unwrap_or_default();
+
+    let is_external =
+        link.starts_with("//") || path.find(':').is_some_and(|i| !path[..i].contains('/'));
+
+    if path.is_empty() || is_external {
+        return true;
+    }
+
+    let target = match path.starts_with('/') {
+        true => htdocs_path(path, htdocs, base_path),
+        false => Path::new(filename).parent().unwrap_or(htdocs).join(path),
+    };
+
+    match target.is_dir() {
+        true => target.join("index.html").is_file(),
+        false => target.is_file(),
+    }
+}
+
+// Maps an absolute URL path to its file under htdocs, dropping the base path only where it
+// ends on a segment boundary so '/docsimg.png' isn't taken for 'img.png' under '/docs'.
+fn htdocs_path(path: &str, htdocs: &Path, base_path: Option<&str>) -> PathBuf {
+    let relative = base_path
+        .and_then(|b| path.strip_prefix(b))
+        .filter(|p| p.is_empty() || p.starts_with('/'))
+        .unwrap_or(path);
+
+    htdocs.join(relative.trim_start_matches('/'))
+}
+
+fn render_file(filename: &str, clappers: &Clappers, site: &Site) -> Result<Vec<Output>, Error> {
+    log_verbose(clappers, &format!("Reading '{}'", filename));
+
+    let contents = read_limited(filename)
+        .map_err(|err| Error::Io(format!("Error reading '{}' ({})", filename, err)))?;
+
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+    let single =
+        |r: Result<String, Error>| r.and_then(|r| Ok(vec![(output_filename(filename)?, r)]));
+    let minify_html = html_minifier(clappers, site)?;
+
+    let (rendered, minify): (_, Minifier) = match filename.rsplit('.').nth(1) {
+        Some("css") => (single(Ok(contents.to_string())), minify_css),
+        Some("html") => (
+            generate_html(filename, contents, clappers, site),
+            minify_html,
+        ),
+        Some("js") => (single(Ok(contents.to_string())), minify_js),
+        Some("less") => (single(compile_less(contents)), minify_css),
+        Some("md") => (
+            single(generate_markdown_page(filename, contents, clappers, site)),
+            minify_html,
+        ),
+        Some(e) if is_passthrough(e, &site.config) => {
+            (single(Ok(contents.to_string())), |c| Ok(c.to_string()))
+        }
+        _ => {
+            return Err(Error::Usage(format!(
+                "Filename '{}' not in the form <name>.(css|html|js|less|md).sssg",
+                filename
+            )))
+        }
+    };
+
+    let outputs = rendered.and_then(|outputs| {
+        outputs
+            .into_iter()
+            .map(|(output_filename, r)| {
+                let r = match (filename.rsplit('.').nth(1), inline_images_below(clappers)) {
+                    (Some("html" | "md"), Some(below)) => {
+                        inline_images(&r, filename, below, clappers, &site.config)?
+                    }
+                    _ => r,
+                };
+
+                let r = match filename.rsplit('.').nth(1) {
+                    Some("html" | "md") if clappers.get_flag("responsive-images") => {
+                        responsive_images(&r, filename, clappers, &site.config)?
+                    }
+                    _ => r,
+                };
+
+                if clappers.get_flag("validate-html")
+                    && matches!(filename.rsplit('.').nth(1), Some("html" | "md"))
+                {
+                    for problem in validate_html(&r) {
+                        warn(format!("invalid HTML in '{}': {}", filename, problem));
+                    }
+                }
+
+                let minified = match output_filename.ends_with(".txt") {
+                    true => r.to_string(),
+                    false => profile("minification", || minify(&r))?,
+                };
+
+                log_verbose(
+                    clappers,
+                    &format!(
+                        "Minified '{}' from {} to {} bytes",
+                        output_filename,
+                        r.len(),
+                        minified.len()
+                    ),
+                );
+
+                Ok(Output {
+                    source: filename.to_string(),
+                    filename: output_filename,
+                    contents: minified,
+                    rendered_size: r.len(),
+                })
+            })
+            .collect::<Result<Vec<Output>, Error>>()
+    });
+
+    let outputs = outputs.map_err(|err| {
+        err.map(|m| format!("Error generating content for '{}' ({})", filename, m))
+    })?;
+
+    if keeps_output(filename) {
+        for output in outputs.iter().filter(|o| Path::new(&o.filename).is_file()) {
+            warn(format!(
+                "overwriting kept output '{}' of '{}'",
+                output.filename, filename
+            ));
+        }
+    }
+
+    Ok(outputs)
+}
+
+fn write_build_manifest(filenames: &[String], htdocs: &str, merge: bool) -> Result<(), Error> {
+    let manifest = format!("{}/{BUILD_MANIFEST}", cwd());
+
+    let mut hashes: BTreeMap<String, String> = match merge {
+        false => BTreeMap::new(),
+        true => read_to_string(&manifest)
+            .ok()
+            .and_then(|m| serde_json::from_str(&m).ok())
+            .unwrap_or_default(),
+    };
+
+    for filename in filenames {
+        let contents = read(filename)
+            .map_err(|err| Error::Io(format!("Error reading '{}' ({})", filename, err)))?;
+
+        let path = filename
+            .strip_prefix(htdocs)
+            .unwrap_or(filename)
+            .trim_start_matches('/');
+
+        hashes.insert(path.to_string(), format!("{:x}", Sha256::digest(&contents)));
+    }
+
+    let contents = serde_json::to_string_pretty(&hashes).map_err(|err| err.to_string())? + "\n";
+
+    write(&manifest, contents)
+        .map_err(|err| Error::Io(format!("Error writing to '{}' ({})", manifest, err)))
+}
+
+fn write_output(output: Output, clappers: &Clappers, site: &Value) -> Result<(), Error> {
+    let Output {
+        filename,
+        mut contents,
+        ..
+    } = output;
+
+    if clappers.get_flag("trailing-newline") && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+
+    let crlf = line_endings(clappers, site)? == "crlf";
+
+    if crlf || contents.contains('\r') {
+        contents = contents.replace("\r\n", "\n");
+
+        if crlf {
+            contents = contents.replace('\n', "\r\n");
+        }
+    }
+
+    log_verbose(clappers, &format!("Writing '{}'", filename));
+
+    if let Some(directory) = Path::new(&filename).parent() {
+        create_dir_all(directory).map_err(|err| {
+            Error::Io(format!(
+                "Error creating '{}' ({})",
+                directory.display(),
+                err
+            ))
+        })?;
+    }
+
+    let compressed = format!("{filename}.gz");
+
+    if clappers.get_flag("precompress")
+        && contents.len() >= min_compress_size(clappers)
+        && [".css", ".html", ".js"]
+            .iter()
+            .any(|e| filename.ends_with(e))
+    {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+
+        log_verbose(clappers, &format!("Writing '{}'", compressed));
+
+        encoder
+            .write_all(contents.as_bytes())
+            .and_then(|_| encoder.finish())
+            .and_then(|c| write(&compressed, c))
+            .map_err(|err| Error::Io(format!("Error writing to '{}' ({})", compressed, err)))?;
+    } else if Path::new(&compressed).is_file() {
+        remove_file(&compressed)
+            .map_err(|err| Error::Io(format!("Error removing file '{}' ({})", compressed, err)))?;
+    }
+
+    write(&filename, contents)
+        .map_err(|err| Error::Io(format!("Error writing to '{}' ({})", filename, err)))
+}
+
+fn copy_targets(site: &Value) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let root = PathBuf::from(cwd());
+    let htdocs = root.join("htdocs");
+    let mut targets = vec![];
+
+    for pattern in site
+        .get("copy")
+        .and_then(|c| c.get("files"))
+        .and_then(|f| f.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let pattern = pattern.as_str().ok_or(Error::Config(String::from(
+            "Values of 'copy.files' must be strings",
+        )))?;
+        let absolute = root.join(pattern).display().to_string();
+
+        let filenames = glob(&absolute)
+            .map_err(|err| Error::Config(format!("Invalid glob '{}' for copy ({})", pattern, err)))?
+            .filter_map(|f| f.ok())
+            .filter(|f| f.is_file());
+
+        for filename in filenames {
+            let destination = filename
+                .strip_prefix(&root)
+                .ok()
+                .and_then(|f| join_within(&htdocs, f))
+                .ok_or(Error::Config(format!(
+                    "Copy of '{}' resolves outside of 'htdocs'",
+                    filename.display()
+                )))?;
+
+            targets.push((filename, destination));
+        }
+    }
+
+    Ok(targets)
+}
+
+fn copy_files(outputs: &[Output], clappers: &Clappers, site: &Value) -> Result<(), Error> {
+    for (filename, destination) in copy_targets(site)? {
+        if outputs
+            .iter()
+            .any(|o| Path::new(&o.filename) == destination)
+        {
+            warn(format!(
+                "not copying '{}' over generated output '{}'",
+                filename.display(),
+                destination.display()
+            ));
+            continue;
+        }
+
+        log_verbose(
+            clappers,
+            &format!(
+                "Copying '{}' to '{}'",
+                filename.display(),
+                destination.display()
+            ),
+        );
+
+        if let Some(directory) = destination.parent() {
+            create_dir_all(directory).map_err(|err| {
+                Error::Io(format!(
+                    "Error creating '{}' ({})",
+                    directory.display(),
+                    err
+                ))
+            })?;
+        }
+
+        copy(&filename, &destination).map_err(|err| {
+            Error::Io(format!(
+                "Error copying '{}' to '{}' ({})",
+                filename.display(),
+                destination.display(),
+                err
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+fn check_output_conflicts(outputs: &[Output]) -> Result<(), Error> {
+    let mut sources: HashMap<&str, Vec<&str>> = HashMap::new();
+
+    for output in outputs {
+        sources
+            .entry(&output.filename)
+            .or_default()
+            .push(&output.source);
+    }
+
+    let mut conflicts = sources
+        .into_iter()
+        .filter(|(_, s)| s.len() > 1)
+        .map(|(f, s)| format!("'{}' from '{}'", f, s.join("', '")))
+        .collect::<Vec<String>>();
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    conflicts.sort();
+
+    Err(Error::Config(format!(
+        "Multiple sources generate the same output ({})",
+        conflicts.join("; ")
+    )))
+}
+
+fn render_tag_pages(
+    filenames: &[String],
+    clappers: &Clappers,
+    site: &Site,
+) -> Result<Vec<Output>, Error> {
+    let template = match site.config.get("tags").and_then(|t| t.get("template")) {
+        None => return Ok(vec![]),
+        Some(t) => t.as_str().ok_or(Error::Config(String::from(
+            "Template for 'tags' is not a string",
+        )))?,
+    };
+
+    let template = read_template(template, &site_config_filename(clappers), clappers)?;
+    let config = config_section(&site.config);
+    let minify_html = html_minifier(clappers, site)?;
+    let mut outputs = vec![];
+
+    for tag in collect_tags(filenames, &site.config)? {
+        let output_filename = tag_filename(&tag.slug, &site.config);
+        let tag_name = tag.name.clone();
+        let values = HashMap::from([(String::from("tag"), tag.name)]);
+        let lists = Lists::from([(String::from("items"), tag.items)]);
+
+        let rendered = render_page(&template, &values, &lists, &config, site)
+            .and_then(|o| Ok((minify_html(&o)?, o.len())));
+
+        let (output, rendered_size) = rendered.map_err(|err| {
+            err.map(|m| format!("Error generating tag page '{}' ({})", output_filename, m))
+        })?;
+
+        outputs.push(Output {
+            source: format!("tag '{}'", tag_name),
+            filename: output_filename,
+            contents: output,
+            rendered_size,
+        });
+    }
+
+    Ok(outputs)
+}
+
+fn render_data_pages(clappers: &Clappers, site: &Site) -> Result<Vec<Output>, Error> {
+    let config = config_section(&site.config);
+    let minify_html = html_minifier(clappers, site)?;
+    let mut templates = HashMap::new();
+    let mut outputs = vec![];
+
+    for page in collect_data_pages(&site.config, &site.data)? {
+        if !templates.contains_key(&page.template) {
+            let template =
+                read_template(&page.template, &site_config_filename(clappers), clappers)?;
+            templates.insert(page.template.clone(), template);
+        }
+
+        let rendered = render_page(
+            &templates[&page.template],
+            &page.values,
+            &page.lists,
+            &config,
+            site,
+        )
+        .and_then(|o| Ok((minify_html(&o)?, o.len())));
+
+        let (output, rendered_size) = rendered.map_err(|err| {
+            err.map(|m| format!("Error generating page '{}' ({})", page.filename, m))
+        })?;
+
+        outputs.push(Output {
+            source: page.source,
+            filename: page.filename,
+            contents: output,
+            rendered_size,
+        });
+    }
+
+    Ok(outputs)
+}
+
+fn collect_data_pages(site: &Value, data: &Value) -> Result<Vec<DataPage>, Error> {
+    let htdocs = PathBuf::from(format!("{}/htdocs", cwd()));
+    let mut pages = vec![];
+
+    let generators = match site.get("generate") {
+        None => return Ok(pages),
+        Some(g) => g.as_table().ok_or(Error::Config(String::from(
+            "Config 'generate' must be a table",
+        )))?,
+    };
+
+    for (name, generator) in generators {
+        let setting = |key: &str| {
+            generator
+                .get(key)
+                .and_then(|v| v.as_str())
+                .ok_or(Error::Config(format!(
+                    "Config 'generate.{}.{}' is not a string",
+                    name, key
+                )))
+        };
+
+        let template = setting("template")?;
+        let source = setting("data")?;
+        let output = setting("output")?;
+
+        let rows = source
+            .split('.')
+            .try_fold(data, |v, key| v.get(key))
+            .and_then(|v| v.as_array())
+            .ok_or(Error::Config(format!(
+                "Data 'data.{}' for 'generate.{}' is not an array",
+                source, name
+            )))?;
+
+        for (i, row) in rows.iter().enumerate() {
+            let row = row.as_table().ok_or(Error::Config(format!(
+                "Row {} of 'data.{}' for 'generate.{}' is not a table",
+                i + 1,
+                source,
+                name
+            )))?;
+
+            let mut values = HashMap::new();
+            let mut lists = Lists::new();
+
+            for (key, value) in row {
+                match value {
+                    Value::Table(_) => (),
+                    Value::Array(items) => {
+                        let items = items
+                            .iter()
+                            .filter_map(|i| i.as_table())
+                            .map(|i| {
+                                i.iter()
+                                    .map(|(k, v)| (k.to_string(), scalar_string(v)))
+                                    .collect()
+                            })
+                            .collect();
+
+                        lists.insert(key.to_string(), items);
+                    }
+                    v => {
+                        values.insert(key.to_string(), scalar_string(v));
+                    }
+                }
+            }
+
+            let mut path = String::new();
+            let mut last = 0;
+
+            for captures in ROW_PLACEHOLDER.captures_iter(output) {
+                let placeholder = captures.get(0).unwrap();
+
+                let value = values.get(&captures[1]).ok_or(Error::Config(format!(
+                    "Output '{}' for 'generate.{}' uses '{}' missing from row {}",
+                    output,
+                    name,
+                    &captures[1],
+                    i + 1
+                )))?;
+
+                path.push_str(&output[last..placeholder.start()]);
+                path.push_str(value);
+                last = placeholder.end();
+            }
+
+            path.push_str(&output[last..]);
+
+            let filename = join_within(&htdocs, Path::new(path.trim_start_matches('/')))
+                .filter(|f| *f != htdocs)
+                .ok_or(Error::Config(format!(
+                    "Output '{}' for row {} of 'generate.{}' resolves outside of htdocs",
+                    path,
+                    i + 1,
+                    name
+                )))?;
+
+            pages.push(DataPage {
+                source: format!("generate '{}' row {}", name, i + 1),
+                template: template.to_string(),
+                filename: filename.display().to_string(),
+                values,
+                lists,
+            });
+        }
+    }
+
+    Ok(pages)
+}
+
+fn collect_tags(filenames: &[String], site: &Value) -> Result<Vec<Tag>, Error> {
+    let mut tags: Vec<Tag> = vec![];
+
+    if site.get("tags").is_none() {
+        return Ok(tags);
+    }
+
+    let mut filenames = filenames
+        .iter()
+        .filter(|f| f.ends_with(".html.sssg"))
+        .collect::<Vec<&String>>();
+
+    filenames.sort();
+
+    for filename in filenames {
+        let contents = read_limited(filename)
+            .map_err(|err| Error::Io(format!("Error reading '{}' ({})", filename, err)))?;
+
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+        let document: Value = from_str(contents).map_err(|err| {
+            Error::Config(format!(
+                "TOML parse error in '{}': {}",
+                filename,
+                toml_error(contents, &err)
+            ))
+        })?;
+
+        let page_tags = document
+            .get("config")
+            .and_then(|c| c.get("tags"))
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t.as_str());
+
+        for tag in page_tags {
+            let slug = slugify(tag);
+
+            if slug.is_empty() {
+                warn(format!(
+                    "tag '{}' in '{}' has no usable characters",
+                    tag, filename
+                ));
+                continue;
+            }
+
+            let item = collection_item(filename)?;
+
+            match tags.iter_mut().find(|t| t.slug == slug) {
+                Some(t) => t.items.push(item),
+                None => tags.push(Tag {
+                    slug,
+                    name: tag.to_string(),
+                    items: vec![item],
+                }),
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+fn tag_filename(slug: &str, site: &Value) -> String {
+    let directory = site
+        .get("tags")
+        .and_then(|t| t.get("directory"))
+        .and_then(|d| d.as_str())
+        .unwrap_or("tags");
+
+    format!(
+        "{}/htdocs/{}/{}.html",
+        cwd(),
+        directory.trim_matches('/'),
+        slug
+    )
+}
+
+fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+fn log_verbose(clappers: &Clappers, message: &str) {
+    if clappers.get_flag("verbose") {
+        eprintln!("{message}");
+    }
+}
+
+fn inline_images_below(clappers: &Clappers) -> Option<u64> {
+    match clappers.get_single("inline-images-below").as_str() {
+        "" => None,
+        b => Some(b.parse::<u64>().unwrap_or_else(
+            |_| die!("Inline image size '{}' must be a number of bytes", b; EXIT_USAGE),
+        )),
+    }
+}
+
+fn inline_images(
+    html: &str,
+    source: &str,
+    below: u64,
+    clappers: &Clappers,
+    site: &Value,
+) -> Result<String, Error> {
+    let mime_types = mime_types(site);
+    let mut output = String::new();
+    let mut last = 0;
+
+    for LocalImage {
+        src_attribute,
+        filename,
+        ..
+    } in local_images(html, source, site)
+    {
+        let mime_type = mime_type(&filename.display().to_string(), &mime_types).to_string();
+
+        if mime_type.starts_with("image/") {
+            record_read(&filename);
+        }
+
+        if !mime_type.starts_with("image/")
+            || metadata(&filename).map_or(true, |m| !m.is_file() || m.len() >= below)
+        {
+            continue;
+        }
+
+        let contents = read(&filename).map_err(|err| {
+            Error::Io(format!("Error reading '{}' ({})", filename.display(), err))
+        })?;
+
+        log_verbose(
+            clappers,
+            &format!(
+                "Inlining '{}' ({} bytes)",
+                filename.display(),
+                contents.len()
+            ),
+        );
+
+        output.push_str(&html[last..src_attribute.start]);
+        output.push_str(&format!(
+            " src=\"data:{};base64,{}\"",
+            mime_type,
+            base64::encode(contents)
+        ));
+        last = src_attribute.end;
+    }
+
+    output.push_str(&html[last..]);
+
+    Ok(output)
+}
+
+fn local_images<'a>(html: &'a str, source: &str, site: &Value) -> Vec<LocalImage<'a>> {
+    let htdocs = PathBuf::from(format!("{}/htdocs", cwd()));
+    let directory = Path::new(source).parent().unwrap_or_else(|| Path::new("."));
+    let base_path = base_path(&config_section(site));
+
+    IMG_TAG
+        .find_iter(html)
+        .filter_map(|tag| {
+            let attribute = match IMG_SRC_ANY.captures(tag.as_str()) {
+                Some(c) if !IMG_SRCSET.is_match(tag.as_str()) => c,
+                _ => return None,
+            };
+
+            let src = attribute
+                .get(1)
+                .or_else(|| attribute.get(2))
+                .or_else(|| attribute.get(3))
+                .map_or("", |s| s.as_str());
+
+            if src.is_empty() || src.starts_with("//") || src.contains(':') {
+                return None;
+            }
+
+            let path = src.split(&['#', '?'][..]).next().unwrap_or_default();
+
+            let filename = match path.starts_with('/') {
+                true => htdocs_path(path, &htdocs, base_path.as_deref()),
+                false => directory.join(path),
+            };
+
+            let whole = attribute.get(0).unwrap();
+
+            Some(LocalImage {
+                tag,
+                src_attribute: tag.start() + whole.start()..tag.start() + whole.end(),
+                src,
+                path,
+                filename,
+            })
+        })
+        .collect()
+}
+
+fn image_widths(site: &Value) -> Result<Vec<i64>, Error> {
+    let mut widths = match site.get("images").and_then(|i| i.get("widths")) {
+        None => vec![480, 960, 1440],
+        Some(w) => w
+            .as_array()
+            .map(|w| {
+                w.iter()
+                    .filter_map(|w| w.as_integer())
+                    .collect::<Vec<i64>>()
+            })
+            .filter(|w| !w.is_empty() && w.iter().all(|w| *w > 0))
+            .ok_or(Error::Config(String::from(
+                "Value of 'images.widths' must be an array of positive integers",
+            )))?,
+    };
+
+    widths.sort();
+
+    Ok(widths)
+}
+
+fn responsive_images(
+    html: &str,
+    source: &str,
+    clappers: &Clappers,
+    site: &Value,
+) -> Result<String, Error> {
+    let widths = image_widths(site)?;
+
+    let sizes = site
+        .get("images")
+        .and_then(|i| i.get("sizes"))
+        .and_then(|s| s.as_str())
+        .unwrap_or("100vw");
+
+    let mut output = String::new();
+    let mut last = 0;
+
+    for image in local_images(html, source, site) {
+        let LocalImage { tag, src, path, .. } = image;
+        let filename = image.filename.display().to_string();
+
+        let extension = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(e) if ["jpeg", "jpg", "png"].contains(&e.to_lowercase().as_str()) => e,
+            _ => continue,
+        };
+
+        record_read(Path::new(&filename));
+
+        let width = match image::image_dimensions(&filename) {
+            Ok((width, _)) => width as i64,
+            Err(_) => continue,
+        };
+
+        let suffix = format!(".{extension}");
+        let mut srcset = vec![];
+
+        for w in widths.iter().filter(|w| **w < width) {
+            let variant = format!("-{w}w{suffix}");
+            let variant_filename = filename.strip_suffix(&suffix).unwrap().to_string() + &variant;
+
+            resize_image(&filename, &variant_filename, *w as u32, clappers)?;
+            record_read(Path::new(&variant_filename));
+            srcset.push(format!(
+                "{}{variant} {w}w",
+                path.strip_suffix(&suffix).unwrap()
+            ));
+        }
+
+        if srcset.is_empty() {
+            continue;
+        }
+
+        srcset.push(format!("{src} {width}w"));
+
+        let tag_end = tag
+            .as_str()
+            .trim_end_matches('>')
+            .trim_end_matches('/')
+            .trim_end();
+
+        output.push_str(&html[last..tag.start()]);
+        output.push_str(&format!(
+            "{} srcset=\"{}\" sizes=\"{}\">",
+            tag_end,
+            escape_attribute(&srcset.join(", ")),
+            escape_attribute(sizes)
+        ));
+        last = tag.end();
+    }
+
+    output.push_str(&html[last..]);
+
+    Ok(output)
+}
+
+fn resize_image(
+    filename: &str,
+    variant: &str,
+    width: u32,
+    clappers: &Clappers,
+) -> Result<(), Error> {
+    let _resizing = RESIZING_IMAGES.lock().unwrap();
+    let modified = |f: &str| metadata(f).and_then(|m| m.modified()).ok();
+
+    if modified(variant).is_some() && modified(variant) >= modified(filename) {
+        return Ok(());
+    }
+
+    log_verbose(
+        clappers,
+        &format!("Resizing '{}' to {}px as '{}'", filename, width, variant),
+    );
+
+    let image = image::open(filename)
+        .map_err(|err| Error::Io(format!("Error reading image '{}' ({})", filename, err)))?;
+
+    image
+        .resize(width, u32::MAX, FilterType::Lanczos3)
+        .save(variant)
+        .map_err(|err| Error::Io(format!("Error writing image '{}' ({})", variant, err)))
+}
+
+fn is_passthrough(extension: &str, site: &Value) -> bool {
+    site.get("build")
+        .and_then(|b| b.get("passthrough"))
+        .and_then(|p| p.as_array())
+        .is_some_and(|p| p.iter().any(|e| e.as_str() == Some(extension)))
+}
+
+fn html_minifier(clappers: &Clappers, site: &Site) -> Result<Minifier, Error> {
+    let level = match clappers.get_single("minify-html") {
+        l if !l.is_empty() => l,
+        _ => config_section(&site.config)
+            .remove("minify_html")
+            .unwrap_or_default(),
+    };
+
+    match level.as_str() {
+        "none" => Ok(|c| Ok(c.to_string())),
+        "conservative" => Ok(minify_html_conservative),
+        "" | "aggressive" => Ok(minify_html),
+        l => Err(Error::Config(format!(
+            "HTML minify level '{}' must be one of none, conservative or aggressive",
+            l
+        ))),
+    }
+}
+
+fn output_filename(filename: &str) -> Result<String, Error> {
+    if let Some(output) = page_config(filename).get("output") {
+        let htdocs = PathBuf::from(format!("{}/htdocs", cwd()));
+
+        let path = Path::new(filename)
+            .parent()
+            .and_then(|d| d.strip_prefix(&htdocs).ok())
+            .and_then(|d| join_within(&htdocs, &d.join(output)))
+            .filter(|p| p != &htdocs)
+            .ok_or(Error::Config(format!(
+                "Output '{}' for '{}' resolves outside of 'htdocs'",
+                output, filename
+            )))?;
+
+        if output.ends_with(".sssg") {
+            return Err(Error::Config(format!(
+                "Output '{}' for '{}' must not be a .sssg file",
+                output, filename
+            )));
+        }
+
+        return Ok(path.display().to_string());
+    }
+
+    Ok(if let Some(name) = filename.strip_suffix(".less.sssg") {
+        format!("{name}.css")
+    } else if let Some(name) = filename.strip_suffix(".md.sssg") {
+        format!("{name}.html")
+    } else {
+        filename.strip_suffix(".sssg").unwrap().to_string()
+    })
+}
+
+fn watch_sources(clappers: &Clappers) {
+    let htdocs = format!("{}/htdocs", cwd());
+    let templates = format!("{}/templates", cwd());
+    let data = format!("{}/data", cwd());
+    let (sender, receiver) = channel();
+
+    let mut watcher: Box<dyn Watcher> = match clappers.get_single("poll").as_str() {
+        "" => Box::new(
+            recommended_watcher(sender)
+                .unwrap_or_else(|err| die!("Error creating file watcher ({})", err; EXIT_IO)),
+        ),
+        // Polling works where OS events aren't delivered (network mounts, some containers),
+        // at the cost of rescanning every file's mtime on each interval.
+        ms => {
+            let interval = ms.parse::<u64>().ok().filter(|ms| *ms > 0).unwrap_or_else(
+                || die!("Poll interval '{}' must be a positive integer", ms; EXIT_USAGE),
+            );
+
+            Box::new(
+                PollWatcher::new(
+                    sender,
+                    Config::default().with_poll_interval(Duration::from_millis(interval)),
+                )
+                .unwrap_or_else(
+                    |err| die!("Error creating polling file watcher ({})", err; EXIT_IO),
+                ),
+            )
+        }
+    };
+
+    for directory in [&htdocs, &templates, &data] {
+        if Path::new(directory).is_dir() {
+            watcher
+                .watch(Path::new(directory), RecursiveMode::Recursive)
+                .unwrap_or_else(|err| die!("Error watching '{}' ({})", directory, err; EXIT_IO));
+        }
+    }
+
+    // Tags each page had when last built, so that tags removed from a page can have their
+    // tag pages regenerated (or deleted when nothing else uses them).
+    let mut page_tags = match load_site(clappers) {
+        Err(err) => {
+            log_event(&err.to_string());
+            HashMap::new()
+        }
+        Ok(site) => collect_sources(&htdocs)
+            .into_iter()
+            .map(|f| {
+                let tags = tag_files(from_ref(&f), &site).unwrap_or_default();
+                (f, tags)
+            })
+            .collect::<HashMap<String, Vec<String>>>(),
+    };
+
+    take_warnings();
+
+    for event in receiver {
+        let event = match event {
+            Ok(e) if e.kind.is_create() || e.kind.is_modify() => e,
+            _ => continue,
+        };
+
+        for path in event.paths {
+            let filename = path.display().to_string();
+
+            if filename.starts_with(&templates) || filename.starts_with(&data) {
+                rebuild_all(clappers);
+            } else if filename.ends_with(".sssg") && path.is_file() {
+                let mut site = match load_site(clappers) {
+                    Ok(site) => site,
+                    Err(err) => {
+                        log_event(&err.to_string());
+                        continue;
+                    }
+                };
+
+                match collect_siblings(&collect_sources(&htdocs)) {
+                    Err(err) => log_event(&err.to_string()),
+                    Ok(siblings) => site.siblings = siblings,
+                }
+
+                let previous = page_tags.remove(&filename).unwrap_or_default();
+
+                if let Ok(tags) = tag_files(from_ref(&filename), &site) {
+                    page_tags.insert(filename.clone(), tags);
+                }
+
+                if rendered_into_other_pages(&filename) {
+                    log_event(&format!(
+                        "Rebuilding everything since '{}' may be rendered into other pages",
+                        filename
+                    ));
+                    rebuild_all(clappers);
+
+                    if let Err(err) = remove_unused_tag_pages(&previous, &site) {
+                        log_event(&err.to_string());
+                    }
+
+                    continue;
+                }
+
+                match generate_file(&filename, clappers, &site) {
+                    Err(err) => log_event(&err.to_string()),
+                    Ok(()) => log_event(&format!("Rebuilt '{}'", filename)),
+                }
+
+                match rebuild_dependents(&filename, &previous, clappers, &site) {
+                    Err(err) => log_event(&err.to_string()),
+                    Ok(rebuilt) => {
+                        for dependent in rebuilt {
+                            log_event(&format!(
+                                "Rebuilt '{}' which depends on '{}'",
+                                dependent, filename
+                            ));
+                        }
+                    }
+                }
+
+                take_warnings();
+            }
+        }
+    }
+}
+
+fn print_minify_report(outputs: &[Output]) {
+    let saved = |before: usize, after: usize| match before {
+        0 => 0.0,
+        b => 100.0 * (b as f64 - after as f64) / b as f64,
+    };
+
+    let (mut before, mut after) = (0, 0);
+
+    for output in outputs
+        .iter()
+        .filter(|o| matches!(o.filename.rsplit('.').next(), Some("html" | "css" | "js")))
+    {
+        before += output.rendered_size;
+        after += output.contents.len();
+
+        println!(
+            "{}: {} -> {} bytes ({:.1}% saved)",
+            output.filename,
+            output.rendered_size,
+            output.contents.len(),
+            saved(output.rendered_size, output.contents.len())
+        );
+    }
+
+    println!(
+        "Total: {} -> {} bytes ({:.1}% saved)",
+        before,
+        after,
+        saved(before, after)
+    );
+}
+
+fn parse_since(since: &str) -> Option<DateTime<Utc>> {
+    let unit = since.trim_start_matches(|c: char| c.is_ascii_digit());
+
+    let seconds = match unit {
+        "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        _ => None,
+    };
+
+    if let (Some(seconds), Ok(n)) = (seconds, since[..since.len() - unit.len()].parse::<i64>()) {
+        return Some(Utc::now() - chrono::Duration::seconds(n * seconds));
+    }
+
+    if let Ok(d) = DateTime::parse_from_rfc3339(since) {
+        return Some(d.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(since, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .and_then(|d| Local.from_local_datetime(&d).single())
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn modified_since(filename: &str, since: DateTime<Utc>) -> bool {
+    metadata(filename)
+        .and_then(|m| m.modified())
+        .map_or(true, |m| DateTime::<Utc>::from(m) > since)
+}
+
+// Every page source under `directory`, sorted, so each pass over the sources sees the
+// same files in the same order.
+fn collect_sources(directory: &str) -> Vec<String> {
+    let mut filenames = WalkDir::new(directory)
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .map(|f| f.path().display().to_string())
+        .filter(|f| f.ends_with(".sssg"))
+        .collect::<Vec<String>>();
+
+    filenames.sort();
+    filenames
+}
+
+fn tag_files(filenames: &[String], site: &Site) -> Result<Vec<String>, Error> {
+    Ok(collect_tags(filenames, &site.config)?
+        .iter()
+        .map(|t| tag_filename(&t.slug, &site.config))
+        .collect())
+}
+
+// A page can be pulled into others with {{render-page}}, either from another page or from a
+// template, so rather than chase those includes (and theirs) a change to it rebuilds the site.
+fn rendered_into_other_pages(filename: &str) -> bool {
+    let changed = canonical_path(Path::new(filename));
+
+    let templates = WalkDir::new(format!("{}/templates", cwd()))
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .filter(|f| f.file_type().is_file())
+        .filter_map(|f| read_to_string(f.path()).ok())
+        .any(|t| RENDER_PAGE.is_match(&t));
+
+    templates
+        || collect_sources(&format!("{}/htdocs", cwd()))
+            .iter()
+            .filter(|f| *f != filename)
+            .any(|source| {
+                let directory = Path::new(source).parent().unwrap_or_else(|| Path::new("."));
+
+                read_limited(source).is_ok_and(|contents| {
+                    RENDER_PAGE
+                        .captures_iter(&contents)
+                        .any(|c| canonical_path(&directory.join(&c[1])) == changed)
+                })
+            })
+}
+
+// Tag pages for tags that no page uses any more are never rendered again, so they're removed
+// rather than left behind listing a page that has dropped the tag.
+fn remove_unused_tag_pages(previous: &[String], site: &Site) -> Result<Vec<String>, Error> {
+    let current = tag_files(&collect_sources(&format!("{}/htdocs", cwd())), site)?;
+    let mut removed = vec![];
+
+    for filename in previous.iter().filter(|f| !current.contains(f)) {
+        match remove_file(filename) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                return Err(Error::Io(format!(
+                    "Error removing '{}' ({})",
+                    filename, err
+                )))
+            }
+            _ => removed.push(filename.to_string()),
+        }
+    }
+
+    Ok(removed)
+}
+
+fn rebuild_dependents(
+    filename: &str,
+    previous_tags: &[String],
+    clappers: &Clappers,
+    site: &Site,
+) -> Result<Vec<String>, Error> {
+    let sources = collect_sources(&format!("{}/htdocs", cwd()));
+    let directory = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+    let mut rebuilt = vec![];
+
+    for source in sources
+        .iter()
+        .filter(|f| f.ends_with(".html.sssg") && *f != filename)
+    {
+        let source_directory = Path::new(source).parent().unwrap_or_else(|| Path::new("."));
+
+        // Pages in the same directory list this one among their siblings.
+        let dependent = source_directory == directory
+            || match page_config(source).remove("collection") {
+                None => false,
+                Some(pattern) => collection_filenames(&pattern, source, source_directory)?
+                    .iter()
+                    .any(|f| f == filename),
+            };
+
+        if dependent {
+            generate_file(source, clappers, site)?;
+            rebuilt.push(source.to_string());
+        }
+    }
+
+    let mut tagged = tag_files(&[filename.to_string()], site)?;
+    tagged.extend(previous_tags.iter().cloned());
+
+    if !tagged.is_empty() {
+        for output in render_tag_pages(&sources, clappers, site)? {
+            if tagged.contains(&output.filename) {
+                rebuilt.push(output.filename.to_string());
+                write_output(output, clappers, &site.config)?;
+            }
+        }
+
+        rebuilt.extend(remove_unused_tag_pages(previous_tags, site)?);
+    }
+
+    Ok(rebuilt)
+}
+
+fn rebuild_all(clappers: &Clappers) -> (usize, Vec<String>) {
+    let mut site = match load_site(clappers) {
+        Ok(site) => site,
+        Err(err) => {
+            log_event(&err.to_string());
+            return (0, vec![err.to_string()]);
+        }
+    };
+
+    let filenames = collect_sources(&format!("{}/htdocs", cwd()));
+
+    let mut errors = vec![];
+    let mut outputs = vec![];
+
+    match collect_siblings(&filenames) {
+        Err(err) => errors.push(err),
+        Ok(siblings) => site.siblings = siblings,
+    }
+
+    for (rendered, _) in render_files(&filenames, clappers, &site) {
+        match rendered {
+            Err(err) => errors.push(err),
+            Ok(o) => outputs.extend(o),
+        }
+    }
+
+    match render_tag_pages(&filenames, clappers, &site) {
+        Err(err) => errors.push(err),
+        Ok(o) => outputs.extend(o),
+    }
+
+    match render_data_pages(clappers, &site) {
+        Err(err) => errors.push(err),
+        Ok(o) => outputs.extend(o),
+    }
+
+    if let Err(err) = copy_files(&outputs, clappers, &site.config) {
+        errors.push(err);
+    }
+
+    take_warnings();
+
+    if let Err(err) = check_output_conflicts(&outputs) {
+        errors.push(err);
+        outputs.clear();
+    }
+
+    for output in outputs {
+        if let Err(err) = write_output(output, clappers, &site.config) {
+            errors.push(err);
+        }
+    }
+
+    let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+
+    for err in &errors {
+        log_event(err);
+    }
+
+    log_event("Rebuilt all files");
+
+    (filenames.len().saturating_sub(errors.len()), errors)
+}
+
+fn toml_error(contents: &str, err: &toml::de::Error) -> String {
+    let (line, column) = match err.line_col() {
+        None => return err.to_string(),
+        Some(position) => position,
+    };
+
+    let source = contents.lines().nth(line).unwrap_or_default();
+    let number = (line + 1).to_string();
+    let gutter = " ".repeat(number.len());
+
+    let indent = source
+        .chars()
+        .take(column)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    format!("{err}\n{gutter} |\n{number} | {source}\n{gutter} | {indent}^")
+}
+
+fn log_event(message: &str) {
+    let _sink = LOG_SINK.lock().unwrap_or_else(|e| e.into_inner());
+    println!("[{}] {message}", Local::now().naive_local());
+}
+
+fn generate_html(
+    filename: &str,
+    contents: &str,
+    clappers: &Clappers,
+    site: &Site,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut parents = vec![canonical_path(Path::new(filename))];
+    let mut outputs = vec![];
+
+    let targets = match from_str::<Value>(contents) {
+        Ok(document) => output_targets(&document)?,
+        Err(_) => vec![String::from("html")],
+    };
+
+    for target in &targets {
+        for (output, html) in render_html(filename, contents, clappers, site, target)? {
+            let html = render_pages(&html, filename, clappers, site, &mut parents)?;
+
+            outputs.push(match target.as_str() {
+                "text" => (target_filename(&output, target), html_text(&html)),
+                _ => (target_filename(&output, target), html),
+            });
+        }
+    }
+
+    Ok(outputs)
+}
+
+fn output_targets(document: &Value) -> Result<Vec<String>, Error> {
+    let targets = match document.get("config").and_then(|c| c.get("outputs")) {
+        None => return Ok(vec![String::from("html")]),
+        Some(t) => t
+            .as_array()
+            .filter(|t| !t.is_empty())
+            .ok_or(Error::Config(String::from(
+                "Config 'outputs' must be a non-empty array of output formats",
+            )))?,
+    };
+
+    targets
+        .iter()
+        .map(|t| match t.as_str() {
+            Some(t @ ("amp" | "html" | "text")) => Ok(t.to_string()),
+            _ => Err(Error::Config(format!(
+                "Unknown output format {} (expected \"html\", \"amp\" or \"text\")",
+                t
+            ))),
+        })
+        .collect()
+}
+
+fn page_targets(filename: &str) -> Vec<String> {
+    read_to_string(filename)
+        .ok()
+        .and_then(|c| from_str::<Value>(c.strip_prefix('\u{feff}').unwrap_or(&c)).ok())
+        .and_then(|d| output_targets(&d).ok())
+        .unwrap_or_else(|| vec![String::from("html")])
+}
+
+fn target_filename(filename: &str, target: &str) -> String {
+    let stem = filename.strip_suffix(".html").unwrap_or(filename);
+
+    match target {
+        "amp" => format!("{stem}.amp.html"),
+        "text" => format!("{stem}.txt"),
+        _ => filename.to_string(),
+    }
+}
+
+fn html_text(html: &str) -> String {
+    let dom = parse_document(RcDom::default(), ParseOpts::default()).one(html);
+    let (title, body) = page_text(&dom);
+
+    match title.is_empty() {
+        true => format!("{body}\n"),
+        false => format!("{title}\n\n{body}\n"),
+    }
+}
+
+fn render_pages(
+    html: &str,
+    filename: &str,
+    clappers: &Clappers,
+    site: &Site,
+    parents: &mut Vec<PathBuf>,
+) -> Result<String, Error> {
+    let directory = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut output = String::new();
+    let mut last = 0;
+
+    for captures in RENDER_PAGE.captures_iter(html) {
+        let directive = captures.get(0).unwrap();
+        let page = directory.join(&captures[1]);
+        let canonical = canonical_path(&page);
+
+        if parents.contains(&canonical) {
+            let cycle = parents
+                .iter()
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ");
+
+            return Err(Error::Template(format!(
+                "Page include cycle detected ({})",
+                cycle
+            )));
+        }
+
+        let page = page.to_string_lossy().to_string();
+
+        let contents = read_limited(&page).map_err(|err| {
+            Error::Io(format!("Error reading rendered page '{}' ({})", page, err))
+        })?;
+
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+        let rendered = render_html(&page, contents, clappers, site, "html")
+            .map_err(|err| err.map(|m| format!("Error rendering page '{}' ({})", page, m)))?
+            .into_iter()
+            .next()
+            .map_or(String::new(), |(_, html)| html);
+
+        parents.push(canonical);
+        let rendered = render_pages(&rendered, &page, clappers, site, parents)?;
+        parents.pop();
+
+        output.push_str(&html[last..directive.start()]);
+        output.push_str(&rendered);
+        last = directive.end();
+    }
+
+    output.push_str(&html[last..]);
+
+    Ok(output)
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn page_sections(
+    document: &Value,
+    site: &Site,
+) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut config = config_section(&site.config);
+    config.extend(config_section(document));
+
+    // --var is for one-off builds, so it wins over the page's own [plaintext].
+    let mut plaintext = get_section("plaintext", document);
+    plaintext.extend(site.vars.clone());
+
+    (config, plaintext)
+}
+
+fn new_page(clappers: &Clappers) {
+    let filename = clappers.get_single("new");
+
+    if !filename.ends_with(".html.sssg") {
+        die!("New page '{}' must be named <name>.html.sssg", filename; EXIT_USAGE);
+    }
+
+    if Path::new(&filename).exists() && !clappers.get_flag("force") {
+        die!("Page '{}' already exists (use --force to overwrite)", filename; EXIT_USAGE);
+    }
+
+    let template = match clappers.get_single("template-name").as_str() {
+        "" => String::from("page.html"),
+        t => t.to_string(),
+    };
+
+    let contents = format!(
+        "[config]\ntemplate = {}\ndate = \"{}\"\n\n[plaintext]\n\n[markdown]\n",
+        Value::String(template),
+        Local::now().format("%Y-%m-%d")
+    );
+
+    if let Some(directory) = Path::new(&filename).parent() {
+        create_dir_all(directory).unwrap_or_else(
+            |err| die!("Error creating '{}' ({})", directory.display(), err; EXIT_IO),
+        );
+    }
+
+    write(&filename, contents)
+        .unwrap_or_else(|err| die!("Error writing to '{}' ({})", filename, err; EXIT_IO));
+
+    println!("Created '{}'", filename);
+}
+
+fn print_config(clappers: &Clappers) {
+    let filename = clappers.get_single("print-config");
+    let site = load_site(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    let contents = read_to_string(&filename)
+        .unwrap_or_else(|err| die!("Error reading '{}' ({})", filename, err; EXIT_IO));
+
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+    let document = match filename.ends_with(".html.sssg") {
+        false => Value::Table(toml::map::Map::new()),
+        true => from_str(contents).unwrap_or_else(|err| {
+            die!(
+                "Error parsing '{}' ({})",
+                filename,
+                toml_error(contents, &err); EXIT_CONFIG
+            )
+        }),
+    };
+
+    let (config, plaintext) = page_sections(&document, &site);
+
+    let sections = BTreeMap::from([
+        ("config", config.into_iter().collect::<BTreeMap<_, _>>()),
+        (
+            "plaintext",
+            plaintext.into_iter().collect::<BTreeMap<_, _>>(),
+        ),
+    ]);
+
+    print!(
+        "{}",
+        toml::to_string(&sections)
+            .unwrap_or_else(|err| die!("Error serialising config ({})", err; EXIT_CONFIG))
+    );
+}
+
+fn render_html(
+    filename: &str,
+    contents: &str,
+    clappers: &Clappers,
+    site: &Site,
+    target: &str,
+) -> Result<Vec<(String, String)>, Error> {
+    let document: Value = profile("parsing", || from_str(contents)).map_err(|err| {
+        Error::Config(format!("TOML parse error: {}", toml_error(contents, &err)))
+    })?;
+    if let Some(template) = document.get("template") {
+        let config = config_section(&site.config);
+        let template = template
+            .as_str()
+            .ok_or(Error::Config(String::from("Raw template is not a string")))?;
+        let output = read_template(template, filename, clappers)?;
+
+        return Ok(vec![(
+            output_filename(filename)?,
+            finish_html(&output, &config),
+        )]);
+    }
+
+    let (mut config, mut plaintext) = page_sections(&document, site);
+
+    if target == "amp" {
+        config.insert(String::from("amp"), String::from("true"));
+    }
+
+    if clappers.get_flag("strict-sections") {
+        for section in ["plaintext", "markdown"] {
+            for key in non_string_keys(section, &document) {
+                warn(format!(
+                    "value of '{}.{}' in '{}' is not a string and was treated as empty",
+                    section, key, filename
+                ));
+            }
+        }
+    }
+
+    if let Some(target) = document.get("config").and_then(|c| c.get("redirect_to")) {
+        let target = target.as_str().ok_or(Error::Config(String::from(
+            "Config 'redirect_to' is not a string",
+        )))?;
+        return Ok(vec![(
+            output_filename(filename)?,
+            redirect_page(target, &config),
+        )]);
+    }
+
+    let mut headings = vec![];
+    let directory = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+
+    let max_level = match config.get("headings_max_level") {
+        Some(n) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=6).contains(n))
+            .ok_or(Error::Config(String::from(
+                "Config 'headings_max_level' must be between 1 and 6",
+            )))?,
+        None => 6,
+    };
+
+    for (name, value) in document
+        .get("markdown")
+        .and_then(|m| m.as_table())
+        .into_iter()
+        .flatten()
+    {
+        let markdown = match value.get("glob") {
+            Some(_) => read_markdown_glob(name, value, directory)?,
+            None => value.as_str().unwrap_or("").to_string(),
+        };
+
+        let html = render_markdown(&markdown, &config);
+        headings.extend(collect_headings(&html, max_level));
+        plaintext.insert(name.to_string(), html);
+    }
+
+    if let Some(parts) = document.get("config").and_then(|c| c.get("parts")) {
+        let html = render_markdown(&read_markdown_parts(parts, directory)?, &config);
+        headings.extend(collect_headings(&html, max_level));
+        plaintext.insert(String::from("body"), html);
+    }
+
+    let mut lists = get_table_arrays(&document);
+    lists.insert(String::from("headings"), headings);
+
+    let template = config.get("template").ok_or(Error::Template(String::from(
+        "Template file not defined in 'config' section",
+    )))?;
+
+    let template = read_template(template, filename, clappers)?;
+
+    let siblings = match site.siblings.get(directory) {
+        Some(siblings) => current_sibling(siblings, filename),
+        None => current_sibling(&directory_siblings(directory)?, filename),
+    };
+
+    lists.insert(String::from("siblings"), siblings);
+
+    let items = match config.get("collection") {
+        Some(pattern) => {
+            let date_formats = match config.get("sort").map(String::as_str) {
+                None => None,
+                Some("date") => Some(date_formats(&document, &site.config)),
+                Some(s) => return Err(Error::Config(format!("Unknown collection sort '{}'", s))),
+            };
+
+            read_collection(pattern, filename, directory, date_formats.as_deref())?
+        }
+        None => {
+            let output = render_page(&template, &plaintext, &lists, &config, site)?;
+            return Ok(vec![(output_filename(filename)?, output)]);
+        }
+    };
+
+    let per_page = items_per_page(&config, items.len())?;
+
+    let page_count = items.len().div_ceil(per_page).max(1);
+
+    (1..=page_count)
+        .map(|page| {
+            let mut values = plaintext.clone();
+            values.insert(String::from("page"), page.to_string());
+            values.insert(String::from("page_count"), page_count.to_string());
+
+            values.insert(
+                String::from("prev"),
+                match page {
+                    1 => String::new(),
+                    _ => page_url(&page_filename(filename, page - 1)?),
+                },
+            );
+
+            values.insert(
+                String::from("next"),
+                match page {
+                    p if p == page_count => String::new(),
+                    _ => page_url(&page_filename(filename, page + 1)?),
+                },
+            );
+
+            let start = (page - 1) * per_page;
+            let end = (start + per_page).min(items.len());
+            lists.insert(String::from("items"), items[start..end].to_vec());
+            let output = render_page(&template, &values, &lists, &config, site)?;
+
+            Ok((page_filename(filename, page)?, output))
+        })
+        .collect()
+}
+
+fn collect_headings(html: &str, max_level: usize) -> Vec<HashMap<String, String>> {
+    HEADING
+        .captures_iter(html)
+        .filter(|c| c[1].parse::<usize>().is_ok_and(|l| l <= max_level))
+        .map(|c| {
+            HashMap::from([
+                (String::from("level"), c[1].to_string()),
+                (String::from("id"), c[2].to_string()),
+                (
+                    String::from("text"),
+                    HTML_TAG_ANY.replace_all(&c[3], "").trim().to_string(),
+                ),
+            ])
+        })
+        .collect()
+}
+
+fn render_page(
+    template: &str,
+    values: &HashMap<String, String>,
+    lists: &Lists,
+    config: &HashMap<String, String>,
+    site: &Site,
+) -> Result<String, Error> {
+    // Config values live under their own `config.` prefix, so `{title}` is always
+    // the page's plaintext even when the config shares a key name.
+    let mut vars = values.clone();
+    vars.extend(
+        config
+            .iter()
+            .map(|(k, v)| (format!("config.{k}"), v.to_string())),
+    );
+
+    let output = profile("templates", || {
+        render_template(template, &vars, lists, &site.data)
+    })?;
+    let output = include_raw(&output, config)?;
+    let output = declare_social_meta(&output, config, values);
+
+    let output = match config.get("amp").map(|a| a.as_str()) {
+        Some("true") => amp_html(&output)?,
+        _ => inline_critical_css(&output, config)?,
+    };
+
+    Ok(finish_html(&output, config))
+}
+
+fn read_collection(
+    pattern: &str,
+    filename: &str,
+    directory: &Path,
+    date_formats: Option<&[String]>,
+) -> Result<Vec<HashMap<String, String>>, Error> {
+    let filenames = collection_filenames(pattern, filename, directory)?;
+
+    if filenames.is_empty() {
+        warn(format!(
+            "glob '{}' for collection matched no pages",
+            directory.join(pattern).display()
+        ));
+    }
+
+    let items = filenames
+        .iter()
+        .map(|f| collection_item(f))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let formats = match date_formats {
+        None => return Ok(items),
+        Some(f) => f,
+    };
+
+    let mut dated = filenames
+        .iter()
+        .zip(items)
+        .map(|(filename, item)| {
+            let date = item.get("date").ok_or(Error::Config(format!(
+                "Collection page '{}' has no date to sort by",
+                filename
+            )))?;
+
+            let parsed = parse_date(date, formats).ok_or(Error::Config(format!(
+                "Date '{}' in '{}' matches none of the date formats ({})",
+                date,
+                filename,
+                formats.join(", ")
+            )))?;
+
+            Ok((parsed, item))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    dated.sort_by_key(|(date, _)| Reverse(*date));
+
+    Ok(dated.into_iter().map(|(_, item)| item).collect())
+}
+
+fn date_formats(document: &Value, site: &Value) -> Vec<String> {
+    let formats = [document, site]
+        .iter()
+        .find_map(|d| d.get("config").and_then(|c| c.get("date_formats")));
+
+    match formats {
+        None => vec![String::from("%Y-%m-%d")],
+        Some(Value::Array(a)) => a.iter().map(scalar_string).collect(),
+        Some(f) => vec![scalar_string(f)],
+    }
+}
+
+fn parse_date(date: &str, formats: &[String]) -> Option<NaiveDateTime> {
+    formats.iter().find_map(|f| {
+        NaiveDateTime::parse_from_str(date, f).ok().or_else(|| {
+            NaiveDate::parse_from_str(date, f)
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+    })
+}
+
+fn collection_filenames(
+    pattern: &str,
+    filename: &str,
+    directory: &Path,
+) -> Result<Vec<String>, Error> {
+    let pattern = directory.join(pattern).display().to_string();
+    record_glob(&pattern);
+
+    let mut filenames = glob(&pattern)
+        .map_err(|err| {
+            Error::Config(format!(
+                "Invalid glob '{}' for collection ({})",
+                pattern, err
+            ))
+        })?
+        .filter_map(|f| f.ok())
+        .map(|f| f.display().to_string())
+        .filter(|f| f.ends_with(".sssg") && f != filename)
+        .collect::<Vec<String>>();
+
+    filenames.sort();
+
+    Ok(filenames)
+}
+
+fn items_per_page(config: &HashMap<String, String>, items: usize) -> Result<usize, Error> {
+    match config.get("per_page") {
+        Some(n) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or(Error::Config(String::from(
+                "Config 'per_page' must be a positive integer",
+            ))),
+        None => Ok(items.max(1)),
+    }
+}
+
+fn collection_item(filename: &str) -> Result<HashMap<String, String>, Error> {
+    let mut item = HashMap::new();
+
+    if filename.ends_with(".html.sssg") {
+        let contents = read_limited(filename)
+            .map_err(|err| Error::Io(format!("Error reading '{}' ({})", filename, err)))?;
+
+        let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+        let document = from_str(contents).map_err(|err| {
+            Error::Config(format!(
+                "TOML parse error in '{}': {}",
+                filename,
+                toml_error(contents, &err)
+            ))
+        })?;
+
+        item.extend(get_section("plaintext", &document));
+
+        if let Some(date) = document.get("config").and_then(|c| c.get("date")) {
+            item.entry(String::from("date"))
+                .or_insert_with(|| scalar_string(date));
+        }
+    }
+
+    item.insert(String::from("url"), page_url(&output_filename(filename)?));
+
+    Ok(item)
+}
+
+// Sibling metadata for every directory with pages, read once per build rather than once for
+// each page that lists its siblings.
+fn collect_siblings(filenames: &[String]) -> Result<HashMap<PathBuf, Siblings>, Error> {
+    let mut siblings: HashMap<PathBuf, Siblings> = HashMap::new();
+
+    for filename in filenames.iter().filter(|f| f.ends_with(".html.sssg")) {
+        let directory = Path::new(filename)
+            .parent()
+            .unwrap_or_else(|| Path::new("."));
+
+        siblings.entry(directory.to_path_buf()).or_default().push((
+            canonical_path(Path::new(filename)),
+            collection_item(filename)?,
+        ));
+    }
+
+    for pages in siblings.values_mut() {
+        pages.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    Ok(siblings)
+}
+
+fn directory_siblings(directory: &Path) -> Result<Siblings, Error> {
+    let filenames = read_dir(directory)
+        .map_err(|err| Error::Io(format!("Error reading '{}' ({})", directory.display(), err)))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path().display().to_string())
+        .collect::<Vec<String>>();
+
+    Ok(collect_siblings(&filenames)?
+        .into_values()
+        .next()
+        .unwrap_or_default())
+}
+
+fn current_sibling(siblings: &Siblings, filename: &str) -> Vec<HashMap<String, String>> {
+    let current = canonical_path(Path::new(filename));
+
+    siblings
+        .iter()
+        .map(|(path, item)| {
+            let mut item = item.clone();
+            item.insert(String::from("is_current"), (*path == current).to_string());
+            item
+        })
+        .collect()
+}
+
+fn page_filename(filename: &str, page: usize) -> Result<String, Error> {
+    let output_filename = output_filename(filename)?;
+
+    let output = Path::new(&output_filename);
+
+    // Index pages already own their directory; any other page gets one named after it, so
+    // that paginated pages side by side don't share their later pages.
+    let directory = match output.file_stem().and_then(|s| s.to_str()) {
+        Some("index") | None => output.with_file_name(""),
+        Some(stem) => output.with_file_name(stem),
+    };
+
+    Ok(match page {
+        1 => output_filename,
+        n => directory
+            .join(format!("page/{n}.html"))
+            .display()
+            .to_string(),
+    })
+}
+
+fn page_url(filename: &str) -> String {
+    let htdocs = format!("{}/htdocs", cwd());
+
+    filename
+        .strip_prefix(&htdocs)
+        .unwrap_or(filename)
+        .to_string()
+}
+
+fn read_markdown_glob(name: &str, value: &Value, directory: &Path) -> Result<String, Error> {
+    let pattern = value
+        .get("glob")
+        .and_then(|g| g.as_str())
+        .ok_or(Error::Config(format!(
+            "Glob for markdown '{}' is not a string",
+            name
+        )))?;
+
+    let pattern = directory.join(pattern).display().to_string();
+    record_glob(&pattern);
+
+    let mut filenames = glob(&pattern)
+        .map_err(|err| {
+            Error::Config(format!(
+                "Invalid glob '{}' for markdown '{}' ({})",
+                pattern, name, err
+            ))
+        })?
+        .filter_map(|f| f.ok())
+        .filter(|f| f.is_file())
+        .collect::<Vec<PathBuf>>();
+
+    match value.get("sort").map(|s| s.as_str()) {
+        None | Some(Some("name")) => filenames.sort(),
+        Some(Some("mtime")) => {
+            filenames.sort_by_key(|f| f.metadata().and_then(|m| m.modified()).ok())
+        }
+        _ => {
+            return Err(Error::Config(format!(
+                "Sort for markdown '{}' must be either \"name\" or \"mtime\"",
+                name
+            )))
+        }
+    }
+
+    if filenames.is_empty() {
+        warn(format!(
+            "glob '{}' for markdown '{}' matched no files",
+            pattern, name
+        ));
+    }
+
+    let mut markdown = vec![];
+
+    for filename in filenames {
+        markdown.push(read_limited(&filename).map_err(|err| {
+            Error::Io(format!("Error reading '{}' ({})", filename.display(), err))
+        })?);
+    }
+
+    Ok(markdown.join("\n\n"))
+}
+
+fn read_markdown_parts(parts: &Value, directory: &Path) -> Result<String, Error> {
+    let parts = parts.as_array().ok_or(Error::Config(String::from(
+        "Config 'parts' must be an array of markdown files",
+    )))?;
+
+    let mut markdown = vec![];
+
+    for part in parts {
+        let filename = directory.join(part.as_str().ok_or(Error::Config(String::from(
+            "Config 'parts' must be an array of markdown files",
+        )))?);
+
+        markdown.push(read_limited(&filename).map_err(|err| {
+            Error::Io(format!("Error reading '{}' ({})", filename.display(), err))
+        })?);
+    }
+
+    Ok(markdown.join("\n\n"))
+}
+
+fn generate_markdown_page(
+    filename: &str,
+    contents: &str,
+    clappers: &Clappers,
+    site: &Site,
+) -> Result<String, Error> {
+    let template_contents = match clappers.get_single("markdown-template").as_str() {
+        "" if !Path::new(&template_path("markdown.html", filename)?).is_file() => {
+            DEFAULT_MARKDOWN_TEMPLATE.to_string()
+        }
+        "" => read_template("markdown.html", filename, clappers)?,
+        t => read_template(t, filename, clappers)?,
+    };
+
+    let config = config_section(&site.config);
+    let mut values = site.vars.clone();
+    values.insert(String::from("body"), render_markdown(contents, &config));
+
+    let output = profile("templates", || {
+        render_template(&template_contents, &values, &Lists::new(), &site.data)
+    })?;
+    let output = include_raw(&output, &config)?;
+    let output = inline_critical_css(&output, &config)?;
+
+    Ok(finish_html(&output, &config))
+}
+
+fn comrak_options(config: &HashMap<String, String>) -> ComrakOptions {
+    let mut options = ComrakOptions::default();
+    options.extension.footnotes = true;
+    options.extension.header_ids =
+        Some(config.get("heading_id_prefix").cloned().unwrap_or_default());
+    options.render.unsafe_ = true;
+    options.render.hardbreaks = config.get("hardbreaks").is_some_and(|h| h == "true");
+    options.render.width = config.get("markdown_width").map_or(0, |w| {
+        w.parse().unwrap_or_else(|_| {
+            warn(format!(
+                "ignoring markdown_width '{}' that is not a number",
+                w
+            ));
+            0
+        })
+    });
+    options
+}
+
+fn render_markdown(markdown: &str, config: &HashMap<String, String>) -> String {
+    let (markdown, shortcodes) = expand_shortcodes(markdown, config);
+    let html = profile("markdown", || {
+        markdown_to_html(&markdown, &comrak_options(config))
+    });
+    let mut references: HashMap<String, usize> = HashMap::new();
+
+    let html = FOOTNOTE_REFERENCE.replace_all(&html, |c: &Captures| {
+        let count = references.entry(c[1].to_string()).or_default();
+        *count += 1;
+
+        match count {
+            1 => c[0].to_string(),
+            n => c[0].replace(
+                &format!("id=\"fnref-{}\"", &c[1]),
+                &format!("id=\"fnref-{}-{n}\"", &c[1]),
+            ),
+        }
+    });
+
+    let html = FOOTNOTE_BACKREFERENCE.replace_all(&html, |c: &Captures| {
+        (1..=references.get(&c[1]).copied().unwrap_or(1))
+            .map(|n| match n {
+                1 => c[0].to_string(),
+                n => c[0]
+                    .replace(
+                        &format!("#fnref-{}\"", &c[1]),
+                        &format!("#fnref-{}-{n}\"", &c[1]),
+                    )
+                    .replace("↩</a>", &format!("↩<sup>{n}</sup></a>")),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    });
+
+    let html = match config.get("footnotes_heading").filter(|h| !h.is_empty()) {
+        None => html.to_string(),
+        Some(heading) => FOOTNOTES_SECTION
+            .replace(&html, |c: &Captures| {
+                format!(
+                    "{}\n<h2 class=\"footnotes-heading\">{}</h2>",
+                    &c[0],
+                    escape_attribute(heading)
+                )
+            })
+            .to_string(),
+    };
+
+    SHORTCODE_PLACEHOLDER
+        .replace_all(&html, |c: &Captures| {
+            let n = c.get(1).or_else(|| c.get(2)).unwrap().as_str();
+            shortcodes[n.parse::<usize>().unwrap()].to_string()
+        })
+        .to_string()
+}
+
+fn expand_shortcodes(markdown: &str, config: &HashMap<String, String>) -> (String, Vec<String>) {
+    let mut output = String::new();
+    let mut shortcodes = vec![];
+    let mut last = 0;
+
+    while let Some(captures) = SHORTCODE.captures(&markdown[last..]) {
+        let opening = captures.get(0).unwrap();
+        let (start, opened) = (last + opening.start(), last + opening.end());
+        let name = &captures[1];
+
+        let closing = Regex::new(&format!(r"\{{\{{%\s*/{}\s*%\}}\}}", regex::escape(name)))
+            .unwrap()
+            .find_at(markdown, opened);
+
+        let (body, end) = match closing {
+            None => ("", opened),
+            Some(c) => (&markdown[opened..c.start()], c.end()),
+        };
+
+        let partial = format!("{}/templates/shortcodes/{}.html", cwd(), name);
+
+        output.push_str(&markdown[last..start]);
+
+        match read_limited(&partial) {
+            Err(_) => {
+                warn(format!("unknown shortcode '{}' left verbatim", name));
+                output.push_str(opening.as_str());
+                last = opened;
+                continue;
+            }
+            Ok(p) => {
+                let html = p.replace("{body}", &render_markdown(body.trim(), config));
+                output.push_str(&format!("\u{e001}{}\u{e001}", shortcodes.len()));
+                shortcodes.push(html.trim_end().to_string());
+            }
+        }
+
+        last = end;
+    }
+
+    output.push_str(&markdown[last..]);
+
+    (output, shortcodes)
+}
+
+fn include_raw(html: &str, config: &HashMap<String, String>) -> Result<String, Error> {
+    let root = PathBuf::from(cwd()).join(config.get("assets_dir").map_or("assets", |a| a));
+    let mut output = String::new();
+    let mut last = 0;
+
+    for captures in INCLUDE_RAW.captures_iter(html) {
+        let directive = captures.get(0).unwrap();
+
+        let filename =
+            join_within(&root, Path::new(&captures[1])).ok_or(Error::Config(format!(
+                "Raw include '{}' resolves outside of its root",
+                &captures[1]
+            )))?;
+
+        let contents = read_limited(&filename).map_err(|err| {
+            Error::Io(format!(
+                "Error reading raw include '{}' ({})",
+                filename.display(),
+                err
+            ))
+        })?;
+
+        output.push_str(&html[last..directive.start()]);
+        output.push_str(&contents);
+        last = directive.end();
+    }
+
+    output.push_str(&html[last..]);
+
+    Ok(output)
+}
+
+fn inline_critical_css(html: &str, config: &HashMap<String, String>) -> Result<String, Error> {
+    let critical = match config.get("critical_css") {
+        Some(c) if !c.is_empty() => c,
+        _ => return Ok(html.to_string()),
+    };
+
+    let root = PathBuf::from(cwd()).join(config.get("assets_dir").map_or("assets", |a| a));
+
+    let filename = join_within(&root, Path::new(critical)).ok_or(Error::Config(format!(
+        "Critical CSS '{}' resolves outside of its root",
+        critical
+    )))?;
+
+    let contents = read_limited(&filename).map_err(|err| {
+        Error::Io(format!(
+            "Error reading critical CSS '{}' ({})",
+            filename.display(),
+            err
+        ))
+    })?;
+
+    let style = format!("<style>{}</style>", minify_css(&contents)?);
+
+    let output = LINK_TAG.replace_all(html, |captures: &Captures| {
+        let link = &captures[0];
+
+        if !STYLESHEET_REL.is_match(link) {
+            return link.to_string();
+        }
+
+        let preload = STYLESHEET_REL.replace(
+            link,
+            r#" rel="preload" as="style" onload="this.onload=null;this.rel='stylesheet'""#,
+        );
+
+        format!("{}<noscript>{}</noscript>", preload, link)
+    });
+
+    Ok(match HEAD_CLOSE_TAG.find(&output) {
+        None => output.to_string(),
+        Some(head) => format!(
+            "{}{}{}",
+            &output[..head.start()],
+            style,
+            &output[head.start()..]
+        ),
+    })
+}
+
+fn render_template(
+    template: &str,
+    values: &HashMap<String, String>,
+    lists: &Lists,
+    data: &Value,
+) -> Result<String, Error> {
+    Ok(fill_template(template, values, lists, data)?
+        .replace(ESCAPED_OPEN_BRACE, "{")
+        .replace(ESCAPED_CLOSE_BRACE, "}"))
+}
+
+// Rendered loop bodies are substituted into their parent template, which is then rendered
+// itself, so braces in values stay escaped until the whole template is done. That way a
+// value is always inserted as written rather than read as template syntax.
+fn fill_template(
+    template: &str,
+    values: &HashMap<String, String>,
+    lists: &Lists,
+    data: &Value,
+) -> Result<String, Error> {
+    let output = render_blocks(template, values, lists, data)?;
+
+    let mut vars = values
+        .iter()
+        .map(|(k, v)| (k.to_string(), escape_braces(v)))
+        .collect::<HashMap<String, String>>();
+
+    for captures in DATA_PLACEHOLDER.captures_iter(&output) {
+        let path = &captures[1][1..];
+
+        let value = path
+            .split('.')
+            .try_fold(data, |v, key| v.get(key))
+            .filter(|v| !v.is_table() && !v.is_array());
+
+        if let Some(value) = value {
+            vars.insert(format!("data.{path}"), escape_braces(&scalar_string(value)));
+        }
+    }
+
+    sssg::render_template(&output, &vars)
+}
+
+fn escape_braces(value: &str) -> String {
+    value
+        .replace('{', ESCAPED_OPEN_BRACE)
+        .replace('}', ESCAPED_CLOSE_BRACE)
+}
+
+fn render_blocks(
+    template: &str,
+    values: &HashMap<String, String>,
+    lists: &Lists,
+    data: &Value,
+) -> Result<String, Error> {
+    let mut tags = TEMPLATE_BLOCK.captures_iter(template);
+
+    let opening = match tags.next() {
+        None => return Ok(template.to_string()),
+        Some(c) if c.get(1).is_none() => {
+            return Err(Error::Template(format!(
+                "Template block '{}' was never opened",
+                &c[0]
+            )))
+        }
+        Some(c) => c,
+    };
+
+    let (kind, name) = (&opening[1], &opening[2]);
+    let mut depth = 0;
+
+    let closing = tags
+        .find(|c| {
+            match c.get(1) {
+                Some(_) => depth += 1,
+                None if depth == 0 => return true,
+                None => depth -= 1,
+            }
+
+            false
+        })
+        .ok_or_else(|| {
+            Error::Template(format!("Template block '{}' is never closed", &opening[0]))
+        })?;
+
+    if &closing[3] != kind {
+        return Err(Error::Template(format!(
+            "Template block '{}' is closed by '{}'",
+            &opening[0], &closing[0]
+        )));
+    }
+
+    let body = &template[opening.get(0).unwrap().end()..closing.get(0).unwrap().start()];
+    let mut output = template[..opening.get(0).unwrap().start()].to_string();
+
+    if kind == "each" {
+        let items = template_list(name, lists, data).ok_or_else(|| {
+            Error::Template(format!("Template list '{}' is missing its value", name))
+        })?;
+
+        for item in items {
+            let mut item_values = values.clone();
+            item_values.extend(item);
+            output.push_str(&fill_template(body, &item_values, lists, data)?);
+        }
+    } else if is_truthy(name, values, lists, data) {
+        output.push_str(&render_blocks(body, values, lists, data)?);
+    }
+
+    output.push_str(&render_blocks(
+        &template[closing.get(0).unwrap().end()..],
+        values,
+        lists,
+        data,
+    )?);
+
+    Ok(output)
+}
+
+fn template_list(name: &str, lists: &Lists, data: &Value) -> Option<Vec<HashMap<String, String>>> {
+    let Some(path) = name.strip_prefix("data.") else {
+        return lists.get(name).cloned();
+    };
+
+    let items = path
+        .split('.')
+        .try_fold(data, |v, key| v.get(key))?
+        .as_array()?
+        .iter()
+        .map(|item| match item.as_table() {
+            Some(table) => flatten_table(table),
+            None => HashMap::from([(String::from("value"), scalar_string(item))]),
+        })
+        .collect();
+
+    Some(items)
+}
+
+fn get_table_arrays(document: &Value) -> Lists {
+    document
+        .as_table()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| {
+            let items = value
+                .as_array()
+                .filter(|a| !a.is_empty() && a.iter().all(Value::is_table))?
+                .iter()
+                .filter_map(Value::as_table)
+                .map(flatten_table)
+                .collect();
+
+            Some((name.to_string(), items))
+        })
+        .collect()
+}
+
+fn flatten_table(table: &toml::map::Map<String, Value>) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+
+    for (key, value) in table {
+        values.insert(key.to_string(), scalar_string(value));
+
+        if let Some(nested) = value.as_table() {
+            values.extend(
+                flatten_table(nested)
+                    .into_iter()
+                    .map(|(k, v)| (format!("{key}_{k}"), v)),
+            );
+        }
+    }
+
+    values
+}
+
+fn is_truthy(name: &str, values: &HashMap<String, String>, lists: &Lists, data: &Value) -> bool {
+    if let Some(items) = template_list(name, lists, data) {
+        return !items.is_empty();
+    }
+
+    let value = match name.strip_prefix("data.") {
+        Some(path) => path
+            .split('.')
+            .try_fold(data, |v, key| v.get(key))
+            .map(scalar_string),
+        None => values.get(name).cloned(),
+    };
+
+    value.is_some_and(|v| !v.is_empty() && v != "false")
+}
+
+fn finish_html(html: &str, config: &HashMap<String, String>) -> String {
+    let output = declare_charset_and_lang(html, config);
+
+    prefix_base_path(&output, config)
+}
+
+fn site_config_filename(clappers: &Clappers) -> String {
+    match clappers.get_single("config").as_str() {
+        "" => format!("{}/sssg.toml", cwd()),
+        c => Path::new(&cwd()).join(c).display().to_string(),
+    }
+}
+
+fn load_site_config(clappers: &Clappers) -> Result<Value, Error> {
+    let filename = site_config_filename(clappers);
+
+    if !Path::new(&filename).is_file() {
+        if !clappers.get_single("config").is_empty() {
+            return Err(Error::Config(format!(
+                "Config file '{}' does not exist",
+                filename
+            )));
+        }
+
+        return Ok(Value::Table(Default::default()));
+    }
+
+    let contents = read_to_string(&filename)
+        .map_err(|err| Error::Io(format!("Error reading '{}' ({})", filename, err)))?;
+
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+
+    from_str(contents).map_err(|err| {
+        Error::Config(format!(
+            "TOML parse error in '{}': {}",
+            filename,
+            toml_error(contents, &err)
+        ))
+    })
+}
+
+fn load_site(clappers: &Clappers) -> Result<Site, Error> {
+    Ok(Site {
+        config: load_site_config(clappers)?,
+        data: load_data()?,
+        vars: cli_vars(clappers)?,
+        siblings: HashMap::new(),
+    })
+}
+
+fn cli_vars(clappers: &Clappers) -> Result<HashMap<String, String>, Error> {
+    clappers
+        .get_multiple("var")
+        .iter()
+        .map(|v| match v.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => Err(Error::Usage(format!(
+                "Variable '{}' must be in the form <key>=<value>",
+                v
+            ))),
+        })
+        .collect()
+}
+
+fn load_data() -> Result<Value, Error> {
+    let directory = format!("{}/data", cwd());
+    let mut data = toml::map::Map::new();
+
+    if !Path::new(&directory).is_dir() {
+        return Ok(Value::Table(data));
+    }
+
+    let mut filenames = read_dir(&directory)
+        .map_err(|err| Error::Io(format!("Error reading '{}' ({})", directory, err)))?
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .filter(|f| f.is_file())
+        .collect::<Vec<PathBuf>>();
+
+    filenames.sort();
+
+    for filename in filenames {
+        let parse = match filename.extension().and_then(|e| e.to_str()) {
+            Some("json") => |c: &str| serde_json::from_str::<Value>(c).map_err(|e| e.to_string()),
+            Some("toml") => |c: &str| from_str::<Value>(c).map_err(|e| e.to_string()),
+            _ => continue,
+        };
+
+        let contents = read_to_string(&filename).map_err(|err| {
+            Error::Io(format!("Error reading '{}' ({})", filename.display(), err))
+        })?;
+
+        let value = parse(&contents).map_err(|err| {
+            Error::Config(format!("Error parsing '{}' ({})", filename.display(), err))
+        })?;
+
+        let name = filename.file_stem().unwrap().to_string_lossy().to_string();
+
+        data.insert(name, value);
+    }
+
+    Ok(Value::Table(data))
+}
+
+fn template_path(name: &str, source: &str) -> Result<String, Error> {
+    let root = PathBuf::from(cwd());
+
+    let path = match name.strip_prefix("./") {
+        Some(relative) => Path::new(source)
+            .parent()
+            .and_then(|d| d.strip_prefix(&root).ok())
+            .and_then(|d| join_within(&root, &d.join(relative))),
+        None => join_within(&root.join("templates"), Path::new(name)),
+    };
+
+    path.map(|p| p.display().to_string())
+        .ok_or(Error::Template(format!(
+            "Template '{}' resolves outside of its root",
+            name
+        )))
+}
+
+fn join_within(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    let mut depth = 0;
+
+    for component in relative.components() {
+        match component {
+            Component::CurDir => (),
+            Component::Normal(c) => {
+                path.push(c);
+                depth += 1;
+            }
+            Component::ParentDir if depth > 0 => {
+                path.pop();
+                depth -= 1;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+fn read_template(name: &str, source: &str, clappers: &Clappers) -> Result<String, Error> {
+    let template = template_path(name, source)?;
+
+    log_verbose(clappers, &format!("Using template '{}'", template));
+
+    read_limited(&template).map_err(|err| {
+        Error::MissingTemplate(format!(
+            "{} '{}' for page '{}' ({})",
+            MISSING_TEMPLATE, template, source, err
+        ))
+    })
+}
+
+fn base_path(config: &HashMap<String, String>) -> Option<String> {
+    match config.get("base_path").map(|b| b.trim_end_matches('/')) {
+        None | Some("") => None,
+        Some(b) if b.starts_with('/') => Some(b.to_string()),
+        Some(b) => Some(format!("/{b}")),
+    }
+}
+
+fn prefix_base_path(html: &str, config: &HashMap<String, String>) -> String {
+    let base_path = match base_path(config) {
+        None => return html.to_string(),
+        Some(b) => b,
+    };
+
+    ROOT_RELATIVE_URL
+        .replace_all(html, |captures: &Captures| {
+            format!("{}{}", &captures[1], prefix_url(&captures[2], &base_path))
+        })
+        .to_string()
+}
+
+fn prefix_url(url: &str, base_path: &str) -> String {
+    if !url.starts_with('/')
+        || url.starts_with("//")
+        || url == base_path
+        || url.starts_with(&format!("{base_path}/"))
+    {
+        url.to_string()
+    } else {
+        format!("{base_path}{url}")
+    }
+}
+
+fn redirect_page(target: &str, config: &HashMap<String, String>) -> String {
+    let target = match base_path(config) {
+        None => target.to_string(),
+        Some(b) => prefix_url(target, &b),
+    };
+
+    let target = escape_attribute(&target);
+
+    let html = format!(
+        concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<title>Redirecting&hellip;</title>\n",
+            "<link rel=\"canonical\" href=\"{target}\">\n",
+            "<meta http-equiv=\"refresh\" content=\"0; url={target}\">\n",
+            "</head>\n<body>\n<p><a href=\"{target}\">{target}</a></p>\n</body>\n</html>\n"
+        ),
+        target = target
+    );
+
+    declare_charset_and_lang(&html, config)
+}
+
+fn declare_social_meta(
+    html: &str,
+    config: &HashMap<String, String>,
+    plaintext: &HashMap<String, String>,
+) -> String {
+    if config.get("open_graph").map(|o| o.as_str()) != Some("true") {
+        return html.to_string();
+    }
+
+    let value = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| config.get(*k).or_else(|| plaintext.get(*k)))
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+    };
+
+    let title = value(&["og_title", "title"]);
+    let description = value(&["og_description", "excerpt"]);
+
+    let image = value(&["og_image"]).map(|i| match config.get("base_url") {
+        Some(b) if !i.contains("://") && !i.starts_with("//") => {
+            format!("{}/{}", b.trim_end_matches('/'), i.trim_start_matches('/'))
+        }
+        _ => i,
+    });
+
+    let mut tags = String::new();
+
+    for (name, content) in [
+        ("title", &title),
+        ("description", &description),
+        ("image", &image),
+    ] {
+        if let Some(c) = content {
+            let c = escape_attribute(c);
+            tags.push_str(&format!("<meta property=\"og:{name}\" content=\"{c}\">"));
+            tags.push_str(&format!("<meta name=\"twitter:{name}\" content=\"{c}\">"));
+        }
+    }
+
+    if tags.is_empty() {
+        return html.to_string();
+    }
+
+    let card = match image {
+        Some(_) => "summary_large_image",
+        None => "summary",
+    };
+
+    tags.push_str(&format!("<meta name=\"twitter:card\" content=\"{card}\">"));
+
+    match HEAD_CLOSE_TAG.find(html) {
+        None => html.to_string(),
+        Some(head) => format!("{}{tags}{}", &html[..head.start()], &html[head.start()..]),
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn declare_charset_and_lang(html: &str, config: &HashMap<String, String>) -> String {
+    let mut output = html.to_string();
+
+    let charset = config.get("charset").map_or("utf-8", |c| c.as_str());
+
+    if !charset.is_empty() && !META_CHARSET.is_match(&output) {
+        if let Some(head) = HEAD_TAG.find(&output) {
+            output.insert_str(head.end(), &format!("<meta charset=\"{charset}\">"));
+        }
+    }
+
+    if let Some(lang) = config.get("lang").filter(|l| !l.is_empty()) {
+        if let Some(tag) = HTML_TAG.captures(&output) {
+            let attributes = tag.get(1).unwrap();
+
+            if !LANG_ATTR.is_match(attributes.as_str()) {
+                output.insert_str(attributes.start(), &format!(" lang=\"{lang}\""));
+            }
+        }
+    }
+
+    output
+}
+
+// Unlike other sections, [config] holds options rather than page text, so booleans
+// and numbers are kept as their TOML representation (e.g. `amp = true` is "true").
+fn config_section(document: &Value) -> HashMap<String, String> {
+    document
+        .get("config")
+        .and_then(|c| c.as_table())
+        .into_iter()
+        .flatten()
+        .map(|(k, v)| (k.to_string(), scalar_string(v)))
+        .collect()
+}
+
+fn get_section(name: &str, document: &Value) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+
+    match document.get(name) {
+        None => values,
+        Some(c) => match c.as_table() {
+            None => values,
+            Some(t) => {
+                for v in t.iter() {
+                    values.insert(v.0.to_string(), v.1.as_str().unwrap_or("").to_string());
+                }
+
+                values
+            }
+        },
+    }
+}
+
+fn non_string_keys(name: &str, document: &Value) -> Vec<String> {
+    // [markdown] values may also be a table naming a glob to read them from.
+    let is_glob = |v: &Value| name == "markdown" && v.get("glob").is_some();
+
+    let mut keys = document
+        .get(name)
+        .and_then(|s| s.as_table())
+        .into_iter()
+        .flatten()
+        .filter(|(_, v)| !v.is_str() && !is_glob(v))
+        .map(|(k, _)| k.to_string())
+        .collect::<Vec<String>>();
+
+    keys.sort();
+    keys
+}
+
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        Value::Array(_) | Value::Table(_) => String::new(),
+        v => v.to_string(),
+    }
+}
+
+enum LessNode {
+    Comment(String),
+    Declaration(String),
+    Rule(String, Vec<LessNode>),
+    Statement(String),
+    Variable(String, String),
+}
+
+fn compile_less(source: &str) -> Result<String, Error> {
+    let source = source.chars().collect::<Vec<char>>();
+    let mut position = 0;
+    let nodes = parse_less_block(&source, &mut position, false)?;
+
+    let mut statements = String::new();
+    let mut output = String::new();
+    emit_less_block(&nodes, &[], &mut vec![], &mut statements, &mut output)?;
+
+    Ok(statements + &output)
+}
+
+fn parse_less_block(
+    source: &[char],
+    position: &mut usize,
+    nested: bool,
+) -> Result<Vec<LessNode>, Error> {
+    let line = |position: usize| source[..position].iter().filter(|c| **c == '\n').count() + 1;
+    let start = *position;
+    let mut nodes = vec![];
+    let mut text = String::new();
+    let mut parentheses = 0;
+
+    while *position < source.len() {
+        let c = source[*position];
+        let next = source.get(*position + 1).copied();
+        *position += 1;
+
+        match c {
+            '"' | '\'' => {
+                text.push(c);
+
+                while *position < source.len() {
+                    let s = source[*position];
+                    *position += 1;
+                    text.push(s);
+
+                    if s == '\\' && *position < source.len() {
+                        text.push(source[*position]);
+                        *position += 1;
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            '/' if next == Some('*') => {
+                let end = (*position + 1..source.len())
+                    .find(|i| source[*i - 1] == '*' && source[*i] == '/' && *i > *position + 1)
+                    .ok_or_else(|| format!("Unterminated comment on line {}", line(*position)))?;
+
+                nodes.push(LessNode::Comment(
+                    source[*position - 1..=end].iter().collect(),
+                ));
+                *position = end + 1;
+            }
+            '/' if next == Some('/') && parentheses == 0 => {
+                while *position < source.len() && source[*position] != '\n' {
+                    *position += 1;
+                }
+            }
+            '(' => {
+                parentheses += 1;
+                text.push(c);
+            }
+            ')' => {
+                parentheses -= 1;
+                text.push(c);
+            }
+            '{' if text.ends_with('@') => {
+                text.push(c);
+
+                while *position < source.len() && source[*position - 1] != '}' {
+                    text.push(source[*position]);
+                    *position += 1;
+                }
+            }
+            '{' => {
+                let children = parse_less_block(source, position, true)?;
+                nodes.push(LessNode::Rule(text.trim().to_string(), children));
+                text.clear();
+            }
+            '}' if nested => {
+                push_less_statement(&mut nodes, &text);
+                return Ok(nodes);
+            }
+            '}' => return Err(format!("Unexpected '}}' on line {}", line(*position)).into()),
+            ';' if parentheses == 0 => {
+                push_less_statement(&mut nodes, &text);
+                text.clear();
+            }
+            _ => text.push(c),
+        }
+    }
+
+    if nested {
+        return Err(format!("Unclosed '{{' on line {}", line(start)).into());
+    }
+
+    push_less_statement(&mut nodes, &text);
+
+    Ok(nodes)
+}
+
+fn push_less_statement(nodes: &mut Vec<LessNode>, text: &str) {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return;
+    }
+
+    match LESS_VARIABLE_DEFINITION.captures(text) {
+        Some(captures) => nodes.push(LessNode::Variable(
+            captures[1].to_string(),
+            captures[2].trim().to_string(),
+        )),
+        None if text.starts_with('@') => nodes.push(LessNode::Statement(text.to_string())),
+        None => nodes.push(LessNode::Declaration(text.to_string())),
+    }
+}
+
+fn emit_less_block(
+    nodes: &[LessNode],
+    selectors: &[String],
+    scopes: &mut Vec<HashMap<String, String>>,
+    statements: &mut String,
+    output: &mut String,
+) -> Result<(), Error> {
+    let mut scope = HashMap::new();
+
+    for node in nodes {
+        if let LessNode::Variable(name, value) = node {
+            scope.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    scopes.push(scope);
+
+    let mut declarations = vec![];
+
+    for node in nodes {
+        if let LessNode::Declaration(declaration) = node {
+            declarations.push(resolve_less_variables(declaration, scopes, 0)?);
+        }
+    }
+
+    if !declarations.is_empty() && selectors.is_empty() {
+        output.push_str(&declarations.join(";"));
+    } else if !declarations.is_empty() {
+        output.push_str(&format!(
+            "{}{{{}}}",
+            selectors.join(","),
+            declarations.join(";")
+        ));
+    }
+
+    for node in nodes {
+        match node {
+            LessNode::Comment(comment) => output.push_str(comment),
+            LessNode::Statement(statement) => {
+                statements.push_str(&resolve_less_at_rule(statement, scopes)?);
+                statements.push(';');
+            }
+            LessNode::Rule(selector, children) => {
+                let selector = resolve_less_at_rule(selector, scopes)?;
+
+                if selector.starts_with("@media") || selector.starts_with("@supports") {
+                    output.push_str(&format!("{selector}{{"));
+                    emit_less_block(children, selectors, scopes, statements, output)?;
+                    output.push('}');
+                } else if selector.starts_with('@') {
+                    output.push_str(&format!("{selector}{{"));
+                    emit_less_block(children, &[], scopes, statements, output)?;
+                    output.push('}');
+                } else {
+                    let nested = nest_less_selectors(selectors, &selector);
+                    emit_less_block(children, &nested, scopes, statements, output)?;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    scopes.pop();
+
+    Ok(())
+}
+
+fn nest_less_selectors(parents: &[String], selector: &str) -> Vec<String> {
+    let mut children = vec![];
+    let mut child = String::new();
+    let mut parentheses = 0;
+
+    for c in selector.chars() {
+        match c {
+            ',' if parentheses == 0 => children.push(std::mem::take(&mut child)),
+            '(' => parentheses += 1,
+            ')' => parentheses -= 1,
+            _ => (),
+        }
+
+        if c != ',' || parentheses > 0 {
+            child.push(c);
+        }
+    }
+
+    children.push(child);
+
+    let children = children.iter().map(|c| c.trim());
+
+    if parents.is_empty() {
+        return children.map(|c| c.replace('&', "")).collect();
+    }
+
+    children
+        .flat_map(|c| {
+            parents.iter().map(move |p| {
+                if c.contains('&') {
+                    c.replace('&', p)
+                } else {
+                    format!("{p} {c}")
+                }
+            })
+        })
+        .collect()
+}
+
+fn resolve_less_at_rule(text: &str, scopes: &[HashMap<String, String>]) -> Result<String, Error> {
+    match text.strip_prefix('@') {
+        None => resolve_less_variables(text, scopes, 0),
+        Some(rule) => {
+            let (keyword, rest) =
+                rule.split_at(rule.find([' ', '(', '"', '\'']).unwrap_or(rule.len()));
+            Ok(format!(
+                "@{keyword}{}",
+                resolve_less_variables(rest, scopes, 0)?
+            ))
+        }
+    }
+}
+
+fn resolve_less_variables(
+    text: &str,
+    scopes: &[HashMap<String, String>],
+    depth: usize,
+) -> Result<String, Error> {
+    if depth > 16 {
+        return Err(format!("Variables in '{}' are recursive", text).into());
+    }
+
+    let mut output = String::new();
+    let mut last = 0;
+
+    for captures in LESS_VARIABLE_REFERENCE.captures_iter(text) {
+        let reference = captures.get(0).unwrap();
+
+        let name = match captures.get(1).or_else(|| captures.get(2)) {
+            None => continue,
+            Some(n) => n.as_str(),
+        };
+
+        let value = scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(name))
+            .ok_or_else(|| format!("Variable '@{}' is undefined", name))?;
+
+        output.push_str(&text[last..reference.start()]);
+        output.push_str(&resolve_less_variables(value, scopes, depth + 1)?);
+        last = reference.end();
+    }
+
+    output.push_str(&text[last..]);
+
+    Ok(output)
+}
+
+enum LogFormat {
+    Clf,
+    Human,
+    Json,
+}
+
+#[derive(Default)]
+struct Stats {
+    bytes: usize,
+    requests: usize,
+    statuses: BTreeMap<u16, usize>,
+}
+
+struct ServeOptions {
+    allow_dotfiles: bool,
+    allowed_hosts: Vec<String>,
+    autoindex: Option<usize>,
+    base_path: Option<String>,
+    brotli_quality: u32,
+    cache_control: Option<String>,
+    content_types: HashMap<String, String>,
+    fallback: Option<String>,
+    handlers: AtomicUsize,
+    health_path: Option<String>,
+    log_file: Option<Mutex<File>>,
+    log_format: LogFormat,
+    max_body: Option<usize>,
+    max_handlers: usize,
+    mime_types: HashMap<String, String>,
+    min_compress_size: usize,
+    mounts: Vec<(String, String)>,
+    negotiate_language: bool,
+    no_cache: Vec<String>,
+    root_file: Option<String>,
+    stats: Mutex<Stats>,
+}
+
+struct Incoming {
+    headers: Vec<Header>,
+    id: String,
+    method: Method,
+    query: Option<String>,
+    remote_addr: SocketAddr,
+    url: String,
+}
+
+struct Reply {
+    body: Vec<u8>,
+    headers: Vec<Header>,
+    status_code: u16,
+}
+
+fn line_endings(clappers: &Clappers, site: &Value) -> Result<String, Error> {
+    match clappers.get_single("line-endings").as_str() {
+        "" => (),
+        l @ ("lf" | "crlf") => return Ok(l.to_string()),
+        l => die!("Line endings '{}' must be either \"lf\" or \"crlf\"", l; EXIT_USAGE),
+    }
+
+    match site.get("build").and_then(|b| b.get("line_endings")) {
+        None => Ok(String::from("lf")),
+        Some(l) => match l.as_str() {
+            Some(l @ ("lf" | "crlf")) => Ok(l.to_string()),
+            _ => Err(Error::Config(String::from(
+                "Config 'build.line_endings' must be either \"lf\" or \"crlf\"",
+            ))),
+        },
+    }
+}
+
+fn min_compress_size(clappers: &Clappers) -> usize {
+    match clappers.get_single("min-compress-size").as_str() {
+        "" => DEFAULT_MIN_COMPRESS_SIZE,
+        m => m.parse::<usize>().unwrap_or_else(
+            |_| die!("Minimum compress size '{}' must be a number of bytes", m; EXIT_USAGE),
+        ),
+    }
+}
+
+// --flag takes precedence over the environment variable, which takes precedence over the default
+fn serve_setting(clappers: &Clappers, name: &str, var: &str, default: &str) -> String {
+    match clappers.get_single(name).as_str() {
+        "" => var_os(var)
+            .map(|v| v.to_string_lossy().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| default.to_string()),
+        v => v.to_string(),
+    }
+}
+
+fn serve_htdocs(clappers: &'static Clappers) {
+    let host = serve_setting(clappers, "host", "SSSG_HOST", "0.0.0.0");
+    let port = serve_setting(clappers, "port", "SSSG_PORT", "1337");
+
+    let threads = match clappers.get_single("threads").as_str() {
+        "" => available_parallelism().map_or(1, |t| t.get()),
+        t => t
+            .parse::<usize>()
+            .ok()
+            .filter(|t| *t > 0)
+            .unwrap_or_else(|| die!("Threads '{}' must be a positive integer", t; EXIT_USAGE)),
+    };
+
+    let timeout = match clappers.get_single("timeout").as_str() {
+        "" => 30,
+        t => t.parse::<u64>().ok().filter(|t| *t > 0).unwrap_or_else(
+            || die!("Timeout '{}' must be a positive number of seconds", t; EXIT_USAGE),
+        ),
+    };
+
+    let min_compress_size = min_compress_size(clappers);
+
+    let log_format = match clappers.get_single("log-format").as_str() {
+        "" | "human" => LogFormat::Human,
+        "clf" => LogFormat::Clf,
+        "json" => LogFormat::Json,
+        f => die!("Log format '{}' must be one of human, clf or json", f; EXIT_USAGE),
+    };
+
+    let log_file = match clappers.get_single("log-file").as_str() {
+        "" => None,
+        f => Some(Mutex::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(f)
+                .unwrap_or_else(|err| die!("Error opening log file '{}' ({})", f, err; EXIT_IO)),
+        )),
+    };
+
+    let site = load_site_config(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    let brotli_quality = match site.get("server").and_then(|s| s.get("brotli_quality")) {
+        None => 5,
+        Some(q) => match q.as_integer() {
+            Some(q @ 0..=11) => q as u32,
+            _ => {
+                die!("Value of 'server.brotli_quality' must be an integer from 0 to 11"; EXIT_CONFIG)
+            }
+        },
+    };
+
+    let max_body = match clappers.get_single("max-body").as_str() {
+        "" => None,
+        n => Some(n.parse::<usize>().unwrap_or_else(
+            |_| die!("Max body size '{}' must be a non-negative integer", n; EXIT_USAGE),
+        )),
+    };
+
+    // A multiple rather than a single so an explicit empty value can be told
+    // apart from the option being absent.
+    let health_path = match clappers.get_multiple("health-path").last() {
+        None => Some(String::from(DEFAULT_HEALTH_PATH)),
+        Some(p) if p.is_empty() => None,
+        Some(p) => Some(p.to_string()),
+    };
+
+    let autoindex = match clappers.get_single("autoindex-limit").as_str() {
+        _ if !clappers.get_flag("autoindex") => None,
+        "" => Some(DEFAULT_AUTOINDEX_LIMIT),
+        n => Some(n.parse::<usize>().ok().filter(|n| *n > 0).unwrap_or_else(
+            || die!("Autoindex limit '{}' must be a positive integer", n; EXIT_USAGE),
+        )),
+    };
+
+    let server = site.get("server");
+
+    let options = Arc::new(ServeOptions {
+        allow_dotfiles: clappers.get_flag("allow-dotfiles"),
+        allowed_hosts: clappers
+            .get_single("allowed-hosts")
+            .split(',')
+            .map(|h| h.trim().to_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect(),
+        autoindex,
+        base_path: base_path(&config_section(&site)),
+        brotli_quality,
+        cache_control: server
+            .and_then(|s| s.get("cache_control"))
+            .and_then(|c| c.as_str())
+            .map(String::from),
+        content_types: content_types(&site),
+        fallback: server
+            .and_then(|s| s.get("fallback"))
+            .and_then(|f| f.as_str())
+            .map(|f| f.trim_start_matches('/').to_string()),
+        handlers: AtomicUsize::new(0),
+        health_path,
+        log_file,
+        log_format,
+        max_body,
+        // Room for one timed out handler per worker before new requests are turned away
+        max_handlers: threads * 2,
+        mime_types: mime_types(&site),
+        min_compress_size,
+        mounts: mounts(clappers),
+        negotiate_language: clappers.get_flag("negotiate-language"),
+        no_cache: server
+            .and_then(|s| s.get("no_cache"))
+            .and_then(|n| n.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|n| n.as_str().map(String::from))
+            .collect(),
+        root_file: root_file(clappers),
+        stats: Mutex::new(Stats::default()),
+    });
+
+    let server = Server::http(format!("{host}:{port}")).unwrap();
+
+    log_event(&format!(
+        "Serving 'htdocs' on http://{host}:{port}/ ({threads} threads, {timeout}s timeout)"
+    ));
+
+    scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for request in server.incoming_requests() {
+                    handle_request(request, clappers, &options, Duration::from_secs(timeout));
+                }
+            });
+        }
+    });
+}
+
+fn handle_request(
+    mut request: Request,
+    clappers: &'static Clappers,
+    options: &Arc<ServeOptions>,
+    timeout: Duration,
+) {
+    let started = Instant::now();
+
+    if options.health_path.as_deref() == request.url().split(['?', '#']).next() {
+        let response = Response::from_string("OK")
+            .with_header(Header::from_bytes("Content-Type", "text/plain; charset=utf-8").unwrap());
+
+        let _ = request.respond(response);
+        return;
+    }
+
+    let id = format!("{:06x}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed));
+
+    let incoming = Incoming {
+        headers: request.headers().to_vec(),
+        id: id.clone(),
+        method: request.method().clone(),
+        remote_addr: *request.remote_addr(),
+        query: request
+            .url()
+            .split('#')
+            .next()
+            .and_then(|u| u.split_once('?'))
+            .map(|(_, q)| q.to_string()),
+        url: sanitise_url(request.url()),
+    };
+
+    let url = incoming.url.clone();
+    let (sender, receiver) = channel();
+
+    if exceeds_max_body(&mut request, options.max_body) {
+        log_event(&format!(
+            "[{id}] Rejected oversized request body for '{}'",
+            url
+        ));
+
+        sender
+            .send(build_reply(
+                &incoming,
+                options,
+                String::from("Payload Too Large").into_bytes(),
+                413,
+                "text/plain; charset=utf-8",
+                vec![Header::from_bytes("Connection", "close").unwrap()],
+            ))
+            .unwrap();
+    } else if options.handlers.fetch_add(1, Ordering::SeqCst) >= options.max_handlers {
+        options.handlers.fetch_sub(1, Ordering::SeqCst);
+
+        log_event(&format!(
+            "[{id}] Rejected '{}' with {} handler(s) still running",
+            url, options.max_handlers
+        ));
+
+        sender
+            .send(build_reply(
+                &incoming,
+                options,
+                String::from("Service Unavailable").into_bytes(),
+                503,
+                "text/plain; charset=utf-8",
+                vec![Header::from_bytes("Retry-After", "1").unwrap()],
+            ))
+            .unwrap();
+    } else {
+        let options = Arc::clone(options);
+        spawn(move || {
+            let reply = catch_unwind(AssertUnwindSafe(|| respond(&incoming, clappers, &options)))
+                .unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|m| m.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+
+                    log_event(&format!(
+                        "[{}] Panic while handling '{}' ({})",
+                        incoming.id, incoming.url, message
+                    ));
+
+                    build_reply(
+                        &incoming,
+                        &options,
+                        String::from("Internal Server Error").into_bytes(),
+                        500,
+                        "text/plain; charset=utf-8",
+                        vec![],
+                    )
+                });
+
+            options.handlers.fetch_sub(1, Ordering::SeqCst);
+            sender.send(reply)
+        });
+    }
+
+    let reply = receiver.recv_timeout(timeout).unwrap_or_else(|_| Reply {
+        body: String::from("Request timed out").into_bytes(),
+        headers: vec![
+            Header::from_bytes("Content-Type", "text/plain; charset=utf-8").unwrap(),
+            Header::from_bytes("Connection", "close").unwrap(),
+        ],
+        status_code: 408,
+    });
+
+    let status_code = reply.status_code;
+    let size = reply.body.len();
+
+    if url != STATS_PATH {
+        let mut stats = options.stats.lock().unwrap();
+        stats.bytes += size;
+        stats.requests += 1;
+        *stats.statuses.entry(status_code).or_default() += 1;
+    }
+    let mut response = Response::from_data(reply.body).with_status_code(StatusCode(status_code));
+
+    for header in reply.headers {
+        response.add_header(header);
+    }
+
+    response.add_header(Header::from_bytes("X-Request-Id", id.as_str()).unwrap());
+
+    let remote_addr = *request.remote_addr();
+    let method = request.method().clone();
+    let raw_url = request.url().to_string();
+    let http_version = request.http_version().clone();
+    let sent = request.respond(response);
+    let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+
+    let line = match options.log_format {
+        LogFormat::Clf => format!(
+            "{} - - [{}] \"{method} {raw_url} HTTP/{http_version}\" {status_code} {size} {elapsed:.3} {id}",
+            remote_addr.ip(),
+            Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+        ),
+        LogFormat::Human => format!(
+            "[{}] [{id}] {status_code} {remote_addr} {} ({elapsed:.3}ms)",
+            Local::now().naive_local(),
+            &url
+        ),
+        LogFormat::Json => json!({
+            "duration_ms": elapsed,
+            "method": method.as_str(),
+            "remote_addr": remote_addr.to_string(),
+            "request_id": id,
+            "size": size,
+            "status": status_code,
+            "timestamp": Utc::now().to_rfc3339(),
+            "url": url,
+        })
+        .to_string(),
+    };
+
+    let sink = LOG_SINK.lock().unwrap_or_else(|e| e.into_inner());
+
+    match &options.log_file {
+        None => println!("{line}"),
+        Some(file) => {
+            if let Err(err) = writeln!(file.lock().unwrap(), "{line}") {
+                eprintln!("Error writing to log file ({})", err);
+            }
+
+            if clappers.get_flag("tee") {
+                println!("{line}");
+            }
+        }
+    }
+
+    drop(sink);
+
+    if sent.is_err() {
+        die!("Error sending response for '{}'", url; EXIT_IO)
+    }
+}
+
+fn exceeds_max_body(request: &mut Request, max_body: Option<usize>) -> bool {
+    let max_body = match max_body {
+        None => return false,
+        Some(m) => m,
+    };
+
+    match request.body_length() {
+        Some(length) => length > max_body,
+        None => {
+            let mut body = request.as_reader().take(max_body as u64 + 1);
+            copy_io(&mut body, &mut sink()).map_or(true, |n| n > max_body as u64)
+        }
+    }
+}
+
+fn respond(request: &Incoming, clappers: &Clappers, options: &ServeOptions) -> Reply {
+    let url = request.url.as_str();
+
+    if !is_allowed_host(request, &options.allowed_hosts) {
+        log_event(&format!(
+            "[{}] Rejected request for '{}' with Host '{}'",
+            request.id,
+            url,
+            header_value(request, "Host").unwrap_or_default()
+        ));
+
+        return build_reply(
+            request,
+            options,
+            String::from("Misdirected Request").into_bytes(),
+            421,
+            "text/plain; charset=utf-8",
+            vec![],
+        );
+    }
+
+    if !options.allow_dotfiles && url.split('/').any(|s| s.starts_with('.')) {
+        log_event(&format!(
+            "[{}] Rejected request for dotfile '{}'",
+            request.id, url
+        ));
+
+        return build_reply(
+            request,
+            options,
+            String::from("Forbidden").into_bytes(),
+            403,
+            "text/plain; charset=utf-8",
+            vec![],
+        );
+    }
+
+    let path = match options.base_path.as_deref() {
+        None => Some(url.to_string()),
+        Some(b) if url == b => Some(String::from("/")),
+        Some(b) => url
+            .strip_prefix(b)
+            .filter(|p| p.starts_with('/'))
+            .map(String::from),
+    };
+
+    let (root, path) = match path.and_then(|p| mount_root(p, &options.mounts)) {
+        None => (String::new(), None),
+        Some((root, path)) => (root, Some(path)),
+    };
+
+    let mut headers = vec![];
+
+    let (message, status_code, content_type) = if url == REBUILD_PATH {
+        let (message, status_code) = trigger_rebuild(request, clappers);
+        (message, status_code, "application/json")
+    } else if url == STATS_PATH {
+        let stats = options.stats.lock().unwrap();
+
+        let message = json!({
+            "bytes": stats.bytes,
+            "requests": stats.requests,
+            "statuses": stats.statuses,
+        });
+
+        (message.to_string().into_bytes(), 200, "application/json")
+    } else if let Some(path) = path.filter(|_| !url.ends_with(".sssg")) {
+        let resolved = resolve_extensionless(&root, &path, clappers, options);
+
+        if resolved.is_none()
+            && !path.ends_with('/')
+            && Path::new(&format!("{root}{path}")).is_dir()
+        {
+            // The url is already decoded, so re-encode it to keep the header ASCII
+            let location = match &request.query {
+                None => format!("{}/", encode_path(url)),
+                Some(q) => format!("{}/?{}", encode_path(url), encode_query(q)),
+            };
+
+            if let Ok(header) = Header::from_bytes("Location", location.as_str()) {
+                headers.push(header);
+            }
+
+            let message = format!(
+                "<a href=\"{}\">Moved Permanently</a>",
+                escape_attribute(&location)
+            );
+
+            return build_reply(
+                request,
+                options,
+                message.into_bytes(),
+                301,
+                "text/html; charset=utf-8",
+                headers,
+            );
+        }
+
+        let filename = if let Some(resolved) = resolved {
+            resolved
+        } else if let (Some(root_file), "/") = (&options.root_file, path.as_str()) {
+            format!("{root}/{root_file}")
+        } else if path.ends_with('/') && options.negotiate_language {
+            let directory = format!("{root}{path}");
+            headers.push(Header::from_bytes("Vary", "Accept-Language").unwrap());
+
+            match negotiate_language(request, &directory) {
+                None => format!("{directory}index.html"),
+                Some(language) => {
+                    headers
+                        .push(Header::from_bytes("Content-Language", language.as_str()).unwrap());
+                    format!("{directory}index.{language}.html")
+                }
+            }
+        } else if path.ends_with('/') {
+            format!("{root}{path}index.html")
+        } else {
+            format!("{root}{path}")
+        };
+
+        let directory = format!("{root}{path}");
+
+        if let Some(limit) = options.autoindex.filter(|_| {
+            path.ends_with('/') && !Path::new(&filename).is_file() && Path::new(&directory).is_dir()
+        }) {
+            return build_reply(
+                request,
+                options,
+                autoindex(&directory, url, limit, options.allow_dotfiles).into_bytes(),
+                200,
+                "text/html; charset=utf-8",
+                headers,
+            );
+        }
+
+        let served = filename.trim_start_matches(&root);
+
+        let cache_control = match is_no_cache(&path, served, &options.no_cache) {
+            true => Some("no-cache, no-store"),
+            false => options.cache_control.as_deref(),
+        };
+
+        if let Some(cache_control) = cache_control {
+            headers.push(Header::from_bytes("Cache-Control", cache_control).unwrap());
+        }
+
+        let content_type = match options
+            .content_types
+            .get(&path)
+            .or_else(|| options.content_types.get(served))
+        {
+            Some(content_type) => content_type.as_str(),
+            None => mime_type(&filename, &options.mime_types),
+        };
+
+        if let Ok(m) = metadata(&filename) {
+            if let Ok(modified) = m.modified() {
+                let modified = DateTime::<Utc>::from(modified);
+                let etag = format!("W/\"{:x}-{:x}\"", m.len(), modified.timestamp());
+
+                let last_modified = modified.format(HTTP_DATE_FORMAT).to_string();
+
+                headers.push(Header::from_bytes("ETag", etag.as_str()).unwrap());
+                headers.push(Header::from_bytes("Last-Modified", last_modified).unwrap());
+
+                if is_not_modified(request, &etag, modified) {
+                    // A 304 stands in for the full reply, so it varies like one.
+                    if varies_by_encoding(options, m.len() as usize, content_type) {
+                        headers.push(Header::from_bytes("Vary", "Accept-Encoding").unwrap());
+                    }
+
+                    return Reply {
+                        body: vec![],
+                        headers,
+                        status_code: 304,
+                    };
+                }
+            }
+        }
+
+        match read(&filename) {
+            Ok(contents) => (contents, 200, content_type),
+            Err(err) if err.kind() == ErrorKind::NotFound => not_found(),
+            Err(err) => (
+                format!("Error reading file '{}' ({})", filename, err)
+                    .as_bytes()
+                    .to_vec(),
+                404,
+                "text/plain; charset=utf-8",
+            ),
+        }
+    } else {
+        not_found()
+    };
+
+    build_reply(
+        request,
+        options,
+        message,
+        status_code,
+        content_type,
+        headers,
+    )
+}
+
+fn autoindex(directory: &str, url: &str, limit: usize, allow_dotfiles: bool) -> String {
+    let mut entries = read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+
+            match e.path().is_dir() {
+                true => format!("{name}/"),
+                false => name,
+            }
+        })
+        .filter(|n| allow_dotfiles || !n.starts_with('.'))
+        .collect::<Vec<String>>();
+
+    entries.sort();
+
+    let title = escape_attribute(&format!("Index of {url}"));
+    let mut html = format!(
+        concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+            "<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+        ),
+        title = title
+    );
+
+    if url != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+
+    for entry in entries.iter().take(limit) {
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            escape_attribute(&encode_path(entry)),
+            escape_attribute(entry)
+        ));
+    }
+
+    html.push_str("</ul>\n");
+
+    if entries.len() > limit {
+        html.push_str(&format!(
+            "<p>{} more entries not shown</p>\n",
+            entries.len() - limit
+        ));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn encode_query(query: &str) -> String {
+    query
+        .bytes()
+        .map(|b| match b {
+            b'!'..=b'~' => (b as char).to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn build_reply(
+    request: &Incoming,
+    options: &ServeOptions,
+    message: Vec<u8>,
+    status_code: u16,
+    content_type: &str,
+    mut headers: Vec<Header>,
+) -> Reply {
+    let varies_by_encoding = varies_by_encoding(options, message.len(), content_type);
+
+    let (body, content_encoding) = compress(request, message, content_type, options);
+
+    headers.push(Header::from_bytes("Content-Type", content_type).unwrap());
+
+    if varies_by_encoding {
+        headers.push(Header::from_bytes("Vary", "Accept-Encoding").unwrap());
+    }
+
+    if let Some(encoding) = content_encoding {
+        headers.push(Header::from_bytes("Content-Encoding", encoding).unwrap());
+    }
+
+    Reply {
+        body,
+        headers,
+        status_code,
+    }
+}
+
+fn is_not_modified(request: &Incoming, etag: &str, modified: DateTime<Utc>) -> bool {
+    if let Some(tags) = header_value(request, "If-None-Match") {
+        let weak = |t: &str| t.trim().trim_start_matches("W/").to_string();
+
+        return tags
+            .split(',')
+            .any(|t| t.trim() == "*" || weak(t) == weak(etag));
+    }
+
+    header_value(request, "If-Modified-Since")
+        .and_then(parse_http_date)
+        .is_some_and(|since| modified.timestamp() <= since.timestamp())
+}
+
+fn is_allowed_host(request: &Incoming, allowed_hosts: &[String]) -> bool {
+    if allowed_hosts.is_empty() {
+        return true;
+    }
+
+    let host = header_value(request, "Host")
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let hostname = match host.rsplit_once(':') {
+        Some((h, port)) if port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => &host,
+    };
+
+    allowed_hosts.iter().any(|h| *h == host || h == hostname)
+}
+
+fn header_value<'a>(request: &'a Incoming, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str().trim())
+}
+
+fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+    [
+        HTTP_DATE_FORMAT,
+        "%A, %d-%b-%y %H:%M:%S GMT",
+        "%a %b %e %H:%M:%S %Y",
+    ]
+    .iter()
+    .find_map(|f| NaiveDateTime::parse_from_str(date, f).ok())
+    .map(|d| DateTime::from_utc(d, Utc))
+}
+
+fn mime_types(site: &Value) -> HashMap<String, String> {
+    let mut mime_types = DEFAULT_MIME_TYPES
+        .iter()
+        .map(|(e, t)| (e.to_string(), t.to_string()))
+        .collect::<HashMap<String, String>>();
+
+    if let Some(server) = site.get("server") {
+        for (extension, mime_type) in get_section("mime", server) {
+            mime_types.insert(extension.trim_start_matches('.').to_lowercase(), mime_type);
+        }
+    }
+
+    mime_types
+}
+
+fn not_found() -> (Vec<u8>, u16, &'static str) {
+    match read(format!("{}/htdocs/404.html", cwd())) {
+        Ok(contents) => (contents, 404, "text/html; charset=utf-8"),
+        Err(_) => (
+            String::from("File not found").as_bytes().to_vec(),
+            404,
+            "text/plain; charset=utf-8",
+        ),
+    }
+}
+
+fn is_no_cache(path: &str, served: &str, no_cache: &[String]) -> bool {
+    no_cache.iter().any(|n| match n.strip_prefix('*') {
+        Some(extension) => served.ends_with(extension),
+        None => n == path || n == served,
+    })
+}
+
+fn resolve_extensionless(
+    root: &str,
+    path: &str,
+    clappers: &Clappers,
+    options: &ServeOptions,
+) -> Option<String> {
+    if path.ends_with('/') || path.rsplit('/').next().unwrap_or_default().contains('.') {
+        return None;
+    }
+
+    let exact = format!("{root}{path}");
+    let html = format!("{exact}.html");
+
+    if Path::new(&exact).is_file() {
+        log_verbose(
+            clappers,
+            &format!("Resolved '{}' to file '{}'", path, exact),
+        );
+        Some(exact)
+    } else if Path::new(&html).is_file() {
+        log_verbose(clappers, &format!("Resolved '{}' to file '{}'", path, html));
+        Some(html)
+    } else if Path::new(&exact).is_dir() {
+        log_verbose(
+            clappers,
+            &format!("Resolved '{}' to directory '{}/'", path, exact),
+        );
+        None
+    } else if let Some(fallback) = &options.fallback {
+        let fallback = format!("{root}/{fallback}");
+        log_verbose(
+            clappers,
+            &format!("Resolved '{}' to fallback '{}'", path, fallback),
+        );
+        Some(fallback)
+    } else {
+        log_verbose(clappers, &format!("Could not resolve '{}'", path));
+        None
+    }
+}
+
+fn mounts(clappers: &Clappers) -> Vec<(String, String)> {
+    let mut mounts = clappers
+        .get_multiple("mount")
+        .iter()
+        .map(|m| match m.split_once('=') {
+            Some((prefix, directory)) if Path::new(directory).is_dir() => (
+                prefix.trim_end_matches('/').to_string(),
+                PathBuf::from(cwd())
+                    .join(directory.trim_end_matches('/'))
+                    .display()
+                    .to_string(),
+            ),
+            Some((_, directory)) => {
+                die!("Mount directory '{}' does not exist", directory; EXIT_USAGE)
+            }
+            None => die!("Mount '{}' must be in the form <prefix>=<dir>", m; EXIT_USAGE),
+        })
+        .collect::<Vec<(String, String)>>();
+
+    mounts.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+    mounts
+}
+
+fn mount_root(path: String, mounts: &[(String, String)]) -> Option<(String, String)> {
+    if mounts.is_empty() {
+        return Some((format!("{}/htdocs", cwd()), path));
+    }
+
+    mounts.iter().find_map(|(prefix, directory)| {
+        path.strip_prefix(prefix.as_str())
+            .filter(|p| p.is_empty() || p.starts_with('/'))
+            .map(|p| (directory.clone(), p.to_string()))
+    })
+}
+
+fn root_file(clappers: &Clappers) -> Option<String> {
+    match clappers.get_single("root-file").trim_start_matches('/') {
+        "" => None,
+        f if Path::new(f)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_))) =>
+        {
+            Some(f.to_string())
+        }
+        f => die!("Root file '{}' must be a path within htdocs", f; EXIT_USAGE),
+    }
+}
+
+fn content_types(site: &Value) -> HashMap<String, String> {
+    match site.get("server") {
+        None => HashMap::new(),
+        Some(server) => get_section("content_type", server)
+            .into_iter()
+            .map(|(path, content_type)| {
+                (format!("/{}", path.trim_start_matches('/')), content_type)
+            })
+            .collect(),
+    }
+}
+
+fn mime_type<'a>(filename: &str, mime_types: &'a HashMap<String, String>) -> &'a str {
+    Path::new(filename)
+        .extension()
+        .and_then(|e| mime_types.get(&e.to_string_lossy().to_lowercase()))
+        .map_or("application/octet-stream", |t| t.as_str())
+}
+
+fn compress(
+    request: &Incoming,
+    contents: Vec<u8>,
+    content_type: &str,
+    options: &ServeOptions,
+) -> (Vec<u8>, Option<&'static str>) {
+    if contents.len() < options.min_compress_size || !is_compressible(content_type) {
+        return (contents, None);
+    }
+
+    if accepts_encoding(request, "br") {
+        let mut writer = CompressorWriter::new(Vec::new(), 4096, options.brotli_quality, 22);
+
+        if writer.write_all(&contents).is_ok() {
+            return (writer.into_inner(), Some("br"));
+        }
+    } else if accepts_encoding(request, "gzip") {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        if let Ok(compressed) = encoder.write_all(&contents).and_then(|_| encoder.finish()) {
+            return (compressed, Some("gzip"));
+        }
+    }
+
+    (contents, None)
+}
+
+fn negotiate_language(request: &Incoming, directory: &str) -> Option<String> {
+    let mut languages = request
+        .headers
+        .iter()
+        .filter(|h| h.field.equiv("Accept-Language"))
+        .flat_map(|h| h.value.as_str().split(','))
+        .filter_map(|range| {
+            let mut parameters = range.split(';').map(|p| p.trim());
+            let name = parameters.next().unwrap_or("").to_lowercase();
+
+            let quality = parameters
+                .find_map(|p| p.strip_prefix("q="))
+                .map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+
+            Some((name, quality)).filter(|(n, q)| !n.is_empty() && n != "*" && *q > 0.0)
+        })
+        .collect::<Vec<(String, f32)>>();
+
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    languages.into_iter().find_map(|(name, _)| {
+        let primary = name.split('-').next().unwrap_or("").to_string();
+
+        vec![name, primary]
+            .into_iter()
+            .filter(|l| l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+            .find(|l| Path::new(&format!("{directory}index.{l}.html")).is_file())
+    })
+}
+
+fn accepts_encoding(request: &Incoming, encoding: &str) -> bool {
+    let mut wildcard = None;
+
+    for coding in request
+        .headers
+        .iter()
+        .filter(|h| h.field.equiv("Accept-Encoding"))
+        .flat_map(|h| h.value.as_str().split(','))
+    {
+        let mut parameters = coding.split(';').map(|p| p.trim());
+        let name = parameters.next().unwrap_or("").to_lowercase();
+
+        let quality = parameters
+            .find_map(|p| p.strip_prefix("q="))
+            .map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+
+        if name == encoding {
+            return quality > 0.0;
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    wildcard.is_some_and(|q| q > 0.0)
+}
+
+fn varies_by_encoding(options: &ServeOptions, len: usize, content_type: &str) -> bool {
+    len >= options.min_compress_size && is_compressible(content_type)
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let mime_type = content_type.split(';').next().unwrap_or("").trim();
+
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+xml")
+        || mime_type.ends_with("+json")
+        || matches!(
+            mime_type,
+            "application/javascript" | "application/json" | "application/xml" | "application/wasm"
+        )
+}
+
+fn trigger_rebuild(request: &Incoming, clappers: &Clappers) -> (Vec<u8>, u16) {
+    let token = clappers.get_single("rebuild-token");
+
+    let authorised = if token.is_empty() {
+        request.remote_addr.ip().is_loopback()
+    } else {
+        request
+            .headers
+            .iter()
+            .any(|h| h.field.equiv("X-Sssg-Token") && h.value.as_str() == token)
+    };
+
+    let (body, status_code) = if !authorised {
+        (json!({ "error": "Forbidden" }), 403)
+    } else if request.method != Method::Post {
+        (json!({ "error": "Method not allowed" }), 405)
+    } else {
+        let (built, errors) = rebuild_all(clappers);
+        let status_code = if errors.is_empty() { 200 } else { 500 };
+
+        (json!({ "built": built, "errors": errors }), status_code)
+    };
+
+    (body.to_string().into_bytes(), status_code)
+}
+
+fn delete_generated_files(clappers: &Clappers) {
+    let filenames = collect_sources(&format!("{}/htdocs", cwd()));
+
+    let site = load_site_config(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    delete_tag_pages(&filenames, clappers);
+    delete_image_variants(&site);
+    delete_data_pages(&site);
+
+    let search_index = format!("{}/htdocs/{SEARCH_INDEX}", cwd());
+
+    if Path::new(&search_index).is_file() {
+        remove_generated(&search_index);
+    }
+
+    if site
+        .get("copy")
+        .and_then(|c| c.get("clean"))
+        .and_then(|c| c.as_bool())
+        == Some(true)
+    {
+        let generated = filenames
+            .iter()
+            .filter_map(|f| planned_outputs(f, &site).ok())
+            .flatten()
+            .collect::<Vec<String>>();
+
+        for (_, destination) in
+            copy_targets(&site).unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+        {
+            if destination.is_file() && !generated.iter().any(|g| Path::new(g) == destination) {
+                remove_file(&destination).unwrap_or_else(|err| {
+                    die!("Error removing file '{}' ({})", destination.display(), err; EXIT_IO)
+                });
+            }
+        }
+    }
+
+    for filename in filenames {
+        if keeps_output(&filename) {
+            continue;
+        }
+
+        let targets = page_targets(&filename);
+        let has_html = targets.iter().any(|t| t == "html");
+
+        match filename.rsplit('.').skip(1).take(1).next() {
+            Some(e)
+                if ["css", "html", "js", "less", "md"].contains(&e) || is_passthrough(e, &site) =>
+            {
+                let generated_filename = output_filename(&filename)
+                    .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+                if has_html {
+                    remove_generated(&generated_filename);
+                }
+            }
+            _ => die!(
+                "Filename '{}' not in the form <name>.(css|html|js|less|md).sssg",
+                filename
+            ),
+        };
+
+        if has_html && page_config(&filename).contains_key("collection") {
+            for page in 2.. {
+                let generated_filename = page_filename(&filename, page)
+                    .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+                if !Path::new(&generated_filename).is_file() {
+                    break;
+                }
+
+                remove_generated(&generated_filename);
+            }
+        }
+
+        for target in targets.iter().filter(|t| *t != "html") {
+            for page in 1.. {
+                let generated_filename = page_filename(&filename, page)
+                    .map(|f| target_filename(&f, target))
+                    .unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+                if !Path::new(&generated_filename).is_file() {
+                    break;
+                }
+
+                remove_generated(&generated_filename);
+            }
+        }
+    }
+}
+
+fn remove_generated(filename: &str) {
+    remove_file(filename)
+        .unwrap_or_else(|err| die!("Error removing file '{}' ({})", filename, err; EXIT_IO));
+
+    let compressed = format!("{filename}.gz");
+
+    if Path::new(&compressed).is_file() {
+        remove_file(&compressed)
+            .unwrap_or_else(|err| die!("Error removing file '{}' ({})", compressed, err; EXIT_IO));
+    }
+}
+
+// Variants are only recognised next to their original, so an image that merely
+// happens to be named like one (e.g. "banner-2x.png") is never removed.
+fn delete_image_variants(site: &Value) {
+    let widths = image_widths(site).unwrap_or_else(|err| die!("{}", err; EXIT_CONFIG));
+
+    let mut filenames = WalkDir::new(format!("{}/htdocs", cwd()))
+        .into_iter()
+        .filter_map(|f| f.ok())
+        .filter(|f| f.file_type().is_file())
+        .map(|f| f.path().to_path_buf())
+        .collect::<Vec<PathBuf>>();
+
+    filenames.sort();
+
+    for filename in filenames {
+        let (stem, extension) = match (
+            filename.file_stem().and_then(|s| s.to_str()),
+            filename.extension().and_then(|e| e.to_str()),
+        ) {
+            (Some(s), Some(e)) if ["jpeg", "jpg", "png"].contains(&e.to_lowercase().as_str()) => {
+                (s, e)
+            }
+            _ => continue,
+        };
+
+        let original = widths.iter().find_map(|w| {
+            stem.strip_suffix(&format!("-{w}w"))
+                .map(|s| filename.with_file_name(format!("{s}.{extension}")))
+        });
+
+        if original.is_some_and(|o| o.is_file()) {
+            remove_generated(&filename.display().to_string());
+        }
+    }
+}
+
+fn delete_tag_pages(filenames: &[String], clappers: &Clappers) {
+    let site = load_site_config(clappers).unwrap_or_else(|err| die!("{}", err; exit_code(&err)));
+
+    for tag in collect_tags(filenames, &site).unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+    {
+        let generated_filename = tag_filename(&tag.slug, &site);
+
+        if Path::new(&generated_filename).is_file() {
+            remove_generated(&generated_filename);
+        }
+    }
+}
+
+fn delete_data_pages(site: &Value) {
+    for page in collect_data_pages(
+        site,
+        &load_data().unwrap_or_else(|err| die!("{}", err; exit_code(&err))),
+    )
+    .unwrap_or_else(|err| die!("{}", err; exit_code(&err)))
+    {
+        if Path::new(&page.filename).is_file() {
+            remove_generated(&page.filename);
+        }
+    }
+}
+
+fn page_config(filename: &str) -> HashMap<String, String> {
+    if !filename.ends_with(".html.sssg") {
+        return HashMap::new();
+    }
+
+    read_to_string(filename)
+        .ok()
+        .and_then(|c| from_str::<Value>(c.strip_prefix('\u{feff}').unwrap_or(&c)).ok())
+        .map(|d| config_section(&d))
+        .unwrap_or_default()
+}
+
+fn keeps_output(filename: &str) -> bool {
+    Path::new(&format!("{filename}keep")).is_file()
+        || page_config(filename)
+            .get("keep_output")
+            .is_some_and(|k| k == "true")
+}
+
+fn show_help() {
+    println!("Usage: sssg [--build] [--clean] [--list] [--serve] [--watch] [OPTIONS]");
+    println!();
+    println!("Options:");
+
+    for (option, description) in HELP_OPTIONS {
+        println!("  {option:<30}{description}");
+    }
+
+    println!();
+    println!("Access log fields for --log-format json (one object per line):");
+
+    for (field, description) in JSON_LOG_FIELDS {
+        println!("  {field:<14}{description}");
+    }
+
+    println!();
+    println!("Template variables, where a later source wins over an earlier one:");
+    for (variable, sources) in TEMPLATE_VARIABLES {
+        println!("  {variable:<18}{sources}");
+    }
+    println!();
+    println!("Build manifest (--build-manifest):");
+    println!("  Written to {BUILD_MANIFEST} in the site root as a JSON object mapping each");
+    println!("  output path, relative to htdocs, to the lowercase hex SHA-256 of its bytes.");
+    println!("  Partial builds (--since or named targets) update the existing manifest.");
+    println!();
+    println!("Exit codes:");
+    println!("  1  build failure");
+    println!("  {EXIT_USAGE}  invalid command line usage");
+    println!("  {EXIT_CONFIG}  config or TOML parse error");
+    println!("  {EXIT_IO}  file or I/O error");
+    println!("  {EXIT_TEMPLATE}  missing template or template variable");
+    println!();
+    println!("Environment (used when the matching flag is not given):");
+    println!("  SSSG_HOST  address to serve on (--host, default 0.0.0.0)");
+    println!("  SSSG_PORT  port to serve on (--port, default 1337)");
+}
+
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Build(_) => 1,
+        Error::Usage(_) => EXIT_USAGE,
+        Error::Config(_) => EXIT_CONFIG,
+        Error::Io(_) => EXIT_IO,
+        Error::MissingVariable(_) | Error::Template(_) | Error::MissingTemplate(_) => EXIT_TEMPLATE,
+    }
 }
@@ -0,0 +1,368 @@
+use lazy_static::lazy_static;
+use minifier::{css, js};
+use minify::html;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+lazy_static! {
+    static ref SANITISE_URL: Regex = Regex::new("[.]{2}").unwrap();
+    static ref DUPLICATE_SLASHES: Regex = Regex::new("/{2,}").unwrap();
+    static ref PRESERVED_ELEMENT: Regex = Regex::new(
+        r"(?is)<pre\b[^>]*>.*?</pre\s*>|<textarea\b[^>]*>.*?</textarea\s*>|<code\b[^>]*>.*?</code\s*>"
+    )
+    .unwrap();
+    static ref PRESERVED_PLACEHOLDER: Regex = Regex::new("\u{e000}([0-9]+)\u{e000}").unwrap();
+    static ref TEMPLATE_PLACEHOLDER: Regex = Regex::new(r"(\{+)(\w+(?:\.[\w-]+)*)\}").unwrap();
+}
+
+/// An error along with the kind of failure it is, which decides the exit code.
+#[derive(Debug)]
+pub enum Error {
+    MissingVariable(String),
+    Build(String),
+    Usage(String),
+    Config(String),
+    Io(String),
+    Template(String),
+    MissingTemplate(String),
+}
+
+impl Error {
+    /// Rewrites the message, e.g. to add context, while keeping the kind of error.
+    pub fn map(self, f: impl FnOnce(String) -> String) -> Error {
+        match self {
+            Error::MissingVariable(_) => Error::Template(f(self.to_string())),
+            Error::Build(m) => Error::Build(f(m)),
+            Error::Usage(m) => Error::Usage(f(m)),
+            Error::Config(m) => Error::Config(f(m)),
+            Error::Io(m) => Error::Io(f(m)),
+            Error::Template(m) => Error::Template(f(m)),
+            Error::MissingTemplate(m) => Error::MissingTemplate(f(m)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingVariable(name) => {
+                write!(f, "Template variable '{}' is missing its value", name)
+            }
+            Error::Build(m)
+            | Error::Usage(m)
+            | Error::Config(m)
+            | Error::Io(m)
+            | Error::Template(m)
+            | Error::MissingTemplate(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Build(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::Build(message.to_string())
+    }
+}
+
+impl error::Error for Error {}
+
+/// Substitutes each `{name}` placeholder in `template` with its value from `vars`, in a
+/// single pass so values are inserted as they are. `{{name}` is left alone.
+pub fn render_template(template: &str, vars: &HashMap<String, String>) -> Result<String, Error> {
+    let mut output = String::new();
+    let mut last = 0;
+
+    for captures in TEMPLATE_PLACEHOLDER.captures_iter(template) {
+        if captures[1].len() > 1 {
+            continue;
+        }
+
+        let placeholder = captures.get(0).unwrap();
+
+        let value = vars
+            .get(&captures[2])
+            .ok_or_else(|| Error::MissingVariable(captures[2].to_string()))?;
+
+        output.push_str(&template[last..placeholder.start()]);
+        output.push_str(value);
+        last = placeholder.end();
+    }
+
+    output.push_str(&template[last..]);
+
+    Ok(output)
+}
+
+/// Minifies a page, collapsing whitespace everywhere.
+pub fn minify_html(contents: &str) -> Result<String, String> {
+    Ok(html::minify(contents))
+}
+
+/// Minifies a page but leaves `<pre>`, `<textarea>` and `<code>` elements byte for byte.
+pub fn minify_html_conservative(contents: &str) -> Result<String, String> {
+    let mut preserved = vec![];
+
+    let protected = PRESERVED_ELEMENT.replace_all(contents, |c: &Captures| {
+        preserved.push(c[0].to_string());
+        format!("\u{e000}{}\u{e000}", preserved.len() - 1)
+    });
+
+    let minified = html::minify(&protected);
+
+    Ok(PRESERVED_PLACEHOLDER
+        .replace_all(&minified, |c: &Captures| {
+            c[1].parse::<usize>()
+                .ok()
+                .and_then(|i| preserved.get(i))
+                .cloned()
+                .unwrap_or_default()
+        })
+        .to_string())
+}
+
+/// Minifies a stylesheet, keeping `/*! ... */` comments at the top of the output.
+pub fn minify_css(contents: &str) -> Result<String, String> {
+    let (comments, source) = important_comments(contents, false);
+    let output = css::minify(&source).map_err(|e| e.to_string())?;
+
+    Ok(prepend_comments(comments, output))
+}
+
+/// Minifies a script, keeping `/*! ... */` comments at the top of the output.
+pub fn minify_js(contents: &str) -> Result<String, String> {
+    let (comments, source) = important_comments(contents, true);
+
+    Ok(prepend_comments(comments, js::minify(&source)))
+}
+
+fn prepend_comments(mut comments: Vec<String>, output: String) -> String {
+    if comments.is_empty() {
+        return output;
+    }
+
+    comments.push(output);
+    comments.join("\n")
+}
+
+// Splits `/*! ... */` comments out of the source, skipping over string, template
+// and (for scripts) regex literals and line comments so their contents are left alone.
+fn important_comments(source: &str, script: bool) -> (Vec<String>, String) {
+    let source = source.chars().collect::<Vec<char>>();
+    let mut comments = vec![];
+    let mut output = String::new();
+    let mut position = 0;
+
+    while position < source.len() {
+        let c = source[position];
+        let next = source.get(position + 1).copied();
+
+        match c {
+            '/' if next == Some('*') => {
+                let end = (position + 2..source.len())
+                    .find(|i| source[*i - 1] == '*' && source[*i] == '/' && *i > position + 2)
+                    .map_or(source.len(), |i| i + 1);
+
+                let comment = source[position..end].iter().collect::<String>();
+
+                match comment.starts_with("/*!") {
+                    true => comments.push(comment),
+                    false => output.push_str(&comment),
+                }
+
+                position = end;
+            }
+            '/' if script && next == Some('/') => {
+                while position < source.len() && source[position] != '\n' {
+                    output.push(source[position]);
+                    position += 1;
+                }
+            }
+            '/' if script && starts_regex(&output) => {
+                let mut class = false;
+                output.push(c);
+                position += 1;
+
+                while position < source.len() && source[position] != '\n' {
+                    let r = source[position];
+                    output.push(r);
+                    position += 1;
+
+                    match r {
+                        '\\' if position < source.len() => {
+                            output.push(source[position]);
+                            position += 1;
+                        }
+                        '[' => class = true,
+                        ']' => class = false,
+                        '/' if !class => break,
+                        _ => (),
+                    }
+                }
+            }
+            '"' | '\'' | '`' if c != '`' || script => {
+                output.push(c);
+                position += 1;
+
+                while position < source.len() {
+                    let s = source[position];
+                    output.push(s);
+                    position += 1;
+
+                    if s == '\\' && position < source.len() {
+                        output.push(source[position]);
+                        position += 1;
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                output.push(c);
+                position += 1;
+            }
+        }
+    }
+
+    (comments, output)
+}
+
+// A '/' begins a regex literal rather than a division when it follows an
+// operator, an opening bracket, or a keyword such as `return`.
+fn starts_regex(output: &str) -> bool {
+    let output = output.trim_end();
+
+    match output.chars().last() {
+        None => true,
+        Some(c) if c.is_alphanumeric() || c == '_' || c == '$' => {
+            let word = output
+                .rsplit(|c: char| !c.is_alphanumeric() && c != '_' && c != '$')
+                .next()
+                .unwrap_or_default();
+
+            [
+                "case", "delete", "in", "of", "return", "throw", "typeof", "void",
+            ]
+            .contains(&word)
+        }
+        Some(c) => "([{,;:=!&|?+-*%<>~^".contains(c),
+    }
+}
+
+/// Reduces a request URL to the path it names: the query and fragment are dropped,
+/// percent-escapes decoded, `..` neutralised and repeated slashes collapsed.
+pub fn sanitise_url(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let mut bytes = vec![];
+    let mut i = 0;
+
+    while i < path.len() {
+        let escaped = path
+            .get(i + 1..i + 3)
+            .filter(|_| path.as_bytes()[i] == b'%')
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+        match escaped {
+            Some(b) => {
+                bytes.push(b);
+                i += 3;
+            }
+            None => {
+                bytes.push(path.as_bytes()[i]);
+                i += 1;
+            }
+        }
+    }
+
+    let decoded = String::from_utf8_lossy(&bytes);
+    let sanitised = SANITISE_URL.replace_all(&decoded, "_");
+
+    DUPLICATE_SLASHES.replace_all(&sanitised, "/").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template_inserts_values_as_written() {
+        let vars = HashMap::from([
+            (String::from("a"), String::from("{b} $1")),
+            (String::from("b"), String::from("B")),
+        ]);
+
+        assert_eq!(
+            render_template("{a}{b} {{include-raw x}}", &vars).unwrap(),
+            "{b} $1B {{include-raw x}}"
+        );
+    }
+
+    #[test]
+    fn sanitise_url_drops_query_from_directory_url() {
+        assert_eq!(sanitise_url("/blog/?page=2#top"), "/blog/");
+    }
+
+    #[test]
+    fn sanitise_url_collapses_doubled_slashes() {
+        assert_eq!(
+            sanitise_url("//blog///posts//p1.html"),
+            "/blog/posts/p1.html"
+        );
+    }
+
+    #[test]
+    fn sanitise_url_decodes_encoded_spaces() {
+        assert_eq!(sanitise_url("/my%20page.html"), "/my page.html");
+    }
+
+    #[test]
+    fn sanitise_url_neutralises_parent_directories() {
+        assert_eq!(sanitise_url("/%2e%2e/etc/passwd"), "/_/etc/passwd");
+    }
+
+    #[test]
+    fn minify_html_conservative_keeps_pre_byte_identical() {
+        let pre = "<pre>  fn main() {\n\n      println!(\"hi\");\n  }\n</pre>";
+        let page =
+            format!("<html>\n  <body>\n    <p>  some   text  </p>\n    {pre}\n  </body>\n</html>");
+
+        let output = minify_html_conservative(&page).unwrap();
+
+        assert!(output.contains(pre));
+        assert!(output.len() < page.len());
+    }
+
+    #[test]
+    fn minify_css_keeps_only_important_comments() {
+        let output =
+            minify_css("/*! MIT License */\n/* layout */\nbody {\n  color: red;\n}\n").unwrap();
+
+        assert!(output.starts_with("/*! MIT License */\n"));
+        assert!(!output.contains("layout"));
+        assert!(output.contains("color:red"));
+    }
+
+    #[test]
+    fn minify_js_keeps_only_important_comments() {
+        let output = minify_js("/*! (c) Example */\n/* helper */\nvar a = 1;\n").unwrap();
+
+        assert!(output.starts_with("/*! (c) Example */\n"));
+        assert!(!output.contains("helper"));
+    }
+
+    #[test]
+    fn minify_js_leaves_comment_markers_in_literals() {
+        let output = minify_js("var s = \"a/*!x*/b\";\nvar r = /\\/*!y/;\n").unwrap();
+
+        assert!(!output.starts_with("/*!"));
+        assert!(output.contains("\"a/*!x*/b\""));
+        assert!(output.contains("/\\/*!y/"));
+    }
+}
@@ -0,0 +1,513 @@
+mod common;
+
+use common::Site;
+
+const PAGE_TEMPLATE: &str = "<html><head><title>{title}</title></head><body>{body}</body></html>";
+
+#[test]
+fn repeated_footnote_references_get_unique_ids_and_backlinks() {
+    let site = Site::new("footnotes");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "page.html"
+
+[plaintext]
+title = "Footnotes"
+
+[markdown]
+body = """
+One[^note] and two[^note].
+
+[^note]: The note.
+"""
+"#,
+    );
+
+    site.build(&[]);
+    let html = site.read("htdocs/index.html");
+
+    assert!(html.contains(r##"<a href="#fn-1" id="fnref-1" data-footnote-ref>"##));
+    assert!(html.contains(r##"<a href="#fn-1" id="fnref-1-2" data-footnote-ref>"##));
+    assert!(html.contains(r##"<a href="#fnref-1" class="footnote-backref""##));
+    assert!(html.contains(r##"<a href="#fnref-1-2" class="footnote-backref""##));
+    assert!(html.contains("↩<sup>2</sup></a>"));
+}
+
+#[test]
+fn toml_errors_exit_with_the_config_code_whatever_the_message_says() {
+    let site = Site::new("toml-exit-code");
+
+    site.write("templates/page.html", PAGE_TEMPLATE)
+        .write("htdocs/index.html.sssg", "x = \"Template variable\" [\n");
+
+    assert_eq!(site.run(&[]).status.code(), Some(3));
+}
+
+#[test]
+fn failing_to_create_an_output_directory_exits_with_the_io_code() {
+    let site = Site::new("io-exit-code");
+
+    site.write("templates/page.html", PAGE_TEMPLATE)
+        .write("htdocs/taken", "")
+        .write(
+            "htdocs/index.html.sssg",
+            r#"
+[config]
+template = "page.html"
+output = "taken/index.html"
+
+[plaintext]
+title = "Taken"
+
+[markdown]
+body = "body"
+"#,
+        );
+
+    assert_eq!(site.run(&[]).status.code(), Some(4));
+}
+
+#[test]
+fn hardbreaks_turns_single_newlines_into_line_breaks() {
+    let site = Site::new("hardbreaks");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "page.html"
+hardbreaks = true
+
+[plaintext]
+title = "Poem"
+
+[markdown]
+body = "roses are red\nviolets are blue"
+"#,
+    );
+
+    site.build(&[]);
+
+    assert!(site
+        .read("htdocs/index.html")
+        .contains("<p>roses are red<br />\nviolets are blue</p>"));
+}
+
+#[test]
+fn single_newlines_stay_soft_without_hardbreaks() {
+    let site = Site::new("softbreaks");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "page.html"
+
+[plaintext]
+title = "Poem"
+
+[markdown]
+body = "roses are red\nviolets are blue"
+"#,
+    );
+
+    site.build(&[]);
+
+    assert!(site
+        .read("htdocs/index.html")
+        .contains("<p>roses are red\nviolets are blue</p>"));
+}
+
+#[test]
+fn array_of_tables_flattens_nested_fields_with_underscores() {
+    let site = Site::new("table-arrays");
+
+    site.write(
+        "templates/products.html",
+        "<ul>{#each products}<li>{name}: {price_amount} {price_currency} ({price_tax_rate})</li>{/each}</ul>",
+    )
+    .write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "products.html"
+
+[[products]]
+name = "Widget"
+price = { amount = "5", currency = "AUD", tax = { rate = "10%" } }
+
+[[products]]
+name = "Gadget"
+
+[products.price]
+amount = "7"
+currency = "NZD"
+
+[products.price.tax]
+rate = "15%"
+"#,
+    );
+
+    site.build(&[]);
+    let html = site.read("htdocs/index.html");
+
+    assert!(html.contains("<li>Widget: 5 AUD (10%)</li><li>Gadget: 7 NZD (15%)</li>"));
+}
+
+#[test]
+fn data_array_of_tables_flattens_nested_fields_with_underscores() {
+    let site = Site::new("data-table-arrays");
+
+    site.write(
+        "templates/team.html",
+        "<ul>{#each data.team.people}<li>{name} ({role_title})</li>{/each}</ul>",
+    )
+    .write(
+        "data/team.toml",
+        r#"
+[[people]]
+name = "Ada"
+role = { title = "Engineer" }
+
+[[people]]
+name = "Grace"
+role = { title = "Admiral" }
+"#,
+    )
+    .write(
+        "htdocs/index.html.sssg",
+        "[config]\ntemplate = \"team.html\"\n",
+    );
+
+    site.build(&[]);
+    let html = site.read("htdocs/index.html");
+
+    assert!(html.contains("<li>Ada (Engineer)</li><li>Grace (Admiral)</li>"));
+}
+
+const MISSING_TEMPLATE_PAGE: &str = r#"
+[config]
+template = "missing.html"
+
+[plaintext]
+title = "Orphan"
+"#;
+
+#[test]
+fn pages_with_a_missing_template_are_skipped_with_a_warning() {
+    let site = Site::new("missing-template-skip");
+    site.write("htdocs/index.html.sssg", MISSING_TEMPLATE_PAGE);
+
+    let output = site.build(&[]);
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("skipped page"));
+}
+
+#[test]
+fn fail_on_missing_template_exits_with_the_template_code() {
+    let site = Site::new("missing-template-fail");
+    site.write("htdocs/index.html.sssg", MISSING_TEMPLATE_PAGE);
+
+    let output = site.run(&["--fail-on-missing-template"]);
+
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn siblings_lists_every_page_in_the_directory_and_marks_the_current_one() {
+    let site = Site::new("siblings");
+
+    let page = |title: &str| {
+        format!("[config]\ntemplate = \"nav.html\"\n\n[plaintext]\ntitle = \"{title}\"\n")
+    };
+
+    site.write(
+        "templates/nav.html",
+        "{#each siblings}<a href=\"{url}\" data-current=\"{is_current}\">{title}</a>{/each}",
+    )
+    .write("htdocs/guide/intro.html.sssg", &page("Intro"))
+    .write("htdocs/guide/setup.html.sssg", &page("Setup"))
+    .write("htdocs/other.html.sssg", &page("Other"));
+
+    site.build(&[]);
+
+    assert_eq!(
+        site.read("htdocs/guide/setup.html"),
+        concat!(
+            r#"<a href="/guide/intro.html" data-current="false">Intro</a>"#,
+            r#"<a href="/guide/setup.html" data-current="true">Setup</a>"#,
+        )
+    );
+}
+
+const GLOB_PAGE: &str = r#"
+[config]
+template = "page.html"
+
+[plaintext]
+title = "Notes"
+
+[markdown.body]
+glob = "../notes/*.md"
+"#;
+
+#[test]
+fn cached_pages_are_rebuilt_when_files_they_read_change() {
+    let site = Site::new("cache-reads");
+
+    site.write("templates/page.html", PAGE_TEMPLATE)
+        .write("notes/a.md", "first")
+        .write("htdocs/index.html.sssg", GLOB_PAGE);
+
+    site.build_with_cache(&[]);
+    site.write("notes/a.md", "edited");
+    site.build_with_cache(&[]);
+
+    assert!(site.read("htdocs/index.html").contains("<p>edited</p>"));
+
+    site.write("notes/b.md", "added");
+    site.build_with_cache(&[]);
+
+    assert!(site.read("htdocs/index.html").contains("<p>added</p>"));
+}
+
+#[test]
+fn cached_pages_repeat_their_warnings() {
+    let site = Site::new("cache-warnings");
+
+    site.write("templates/page.html", PAGE_TEMPLATE)
+        .write("htdocs/index.html.sssg", GLOB_PAGE);
+
+    site.build_with_cache(&[]);
+    let output = site.build_with_cache(&["--verbose"]);
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Using cached output"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("matched no files"));
+}
+
+#[test]
+fn check_external_fails_once_when_curl_is_missing() {
+    let site = Site::new("external-without-curl");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "page.html"
+
+[plaintext]
+title = "Links"
+
+[markdown]
+body = "[one](https://example.com/one) and [two](https://example.com/two)"
+"#,
+    );
+
+    let output = site.run_with_env(&[("PATH", "")], &["--no-cache", "--check-external"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr.contains("--check-external needs"), "{}", stderr);
+    assert!(!stderr.contains("dead external link"), "{}", stderr);
+}
+
+#[test]
+fn verbose_logging_keeps_stdin_output_clean() {
+    let site = Site::new("stdin-verbose");
+    site.write("templates/page.html", PAGE_TEMPLATE);
+
+    let output = site.pipe(
+        &["--verbose", "--minify-html", "none"],
+        "[config]\ntemplate = \"page.html\"\n\n[plaintext]\ntitle = \"Piped\"\n\n[markdown]\nbody = \"body\"\n",
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("<html>"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Using template"));
+}
+
+#[test]
+fn data_values_with_braces_render_literally() {
+    let site = Site::new("data-braces");
+
+    site.write(
+        "templates/page.html",
+        "<p>{data.site.tagline}</p><ul>{#each data.site.links}<li>{label}</li>{/each}</ul>",
+    )
+    .write(
+        "data/site.toml",
+        r#"
+tagline = "use {braces} here"
+
+[[links]]
+label = "{x} and $1"
+"#,
+    )
+    .write(
+        "htdocs/index.html.sssg",
+        "[config]\ntemplate = \"page.html\"\n",
+    );
+
+    site.build(&[]);
+
+    assert_eq!(
+        site.read("htdocs/index.html"),
+        "<p>use {braces} here</p><ul><li>{x} and $1</li></ul>"
+    );
+}
+
+#[test]
+fn config_values_with_braces_render_literally() {
+    let site = Site::new("config-braces");
+
+    site.write("templates/page.html", "<p>{config.motto}</p>")
+        .write(
+            "htdocs/index.html.sssg",
+            "[config]\ntemplate = \"page.html\"\nmotto = \"{oops}\"\n",
+        );
+
+    site.build(&[]);
+
+    assert_eq!(site.read("htdocs/index.html"), "<p>{oops}</p>");
+}
+
+#[test]
+fn paginated_pages_in_one_directory_get_their_own_page_directories() {
+    let site = Site::new("pagination");
+
+    let listing = |pattern: &str| {
+        format!("[config]\ntemplate = \"list.html\"\ncollection = \"{pattern}\"\nper_page = 1\n")
+    };
+
+    site.write("templates/list.html", "{#each items}<p>{title}</p>{/each}")
+        .write("templates/post.html", "<h1>{title}</h1>")
+        .write("htdocs/blog.html.sssg", &listing("posts/*.html.sssg"))
+        .write("htdocs/news.html.sssg", &listing("items/*.html.sssg"))
+        .write(
+            "htdocs/archive/index.html.sssg",
+            &listing("../posts/*.html.sssg"),
+        );
+
+    for (path, title) in [
+        ("posts/a", "Post A"),
+        ("posts/b", "Post B"),
+        ("items/a", "News A"),
+        ("items/b", "News B"),
+    ] {
+        site.write(
+            &format!("htdocs/{path}.html.sssg"),
+            &format!("[config]\ntemplate = \"post.html\"\n\n[plaintext]\ntitle = \"{title}\"\n"),
+        );
+    }
+
+    site.build(&[]);
+
+    assert_eq!(site.read("htdocs/blog.html"), "<p>Post A</p>");
+    assert_eq!(site.read("htdocs/blog/page/2.html"), "<p>Post B</p>");
+    assert_eq!(site.read("htdocs/news.html"), "<p>News A</p>");
+    assert_eq!(site.read("htdocs/news/page/2.html"), "<p>News B</p>");
+    assert_eq!(site.read("htdocs/archive/page/2.html"), "<p>Post B</p>");
+}
+
+#[test]
+fn site_config_errors_show_the_line_and_a_caret() {
+    let site = Site::new("site-config-error");
+
+    site.write("sssg.toml", "[site]\nname = \"x\"\nbroken =\n")
+        .write("templates/page.html", PAGE_TEMPLATE);
+
+    let output = site.run(&[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr.contains("sssg.toml"), "{}", stderr);
+    assert!(stderr.contains("3 | broken ="), "{}", stderr);
+    assert!(stderr.contains('^'), "{}", stderr);
+}
+
+#[test]
+fn clean_removes_the_search_index() {
+    let site = Site::new("clean-search-index");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        "[config]\ntemplate = \"page.html\"\n\n[plaintext]\ntitle = \"Home\"\n\n[markdown]\nbody = \"hello\"\n",
+    );
+
+    site.build(&["--search-index"]);
+    assert!(site.root.join("htdocs/search-index.json").is_file());
+
+    site.clean();
+
+    assert!(!site.root.join("htdocs/search-index.json").exists());
+    assert!(!site.root.join("htdocs/index.html").exists());
+}
+
+#[test]
+fn strict_sections_warns_about_every_non_string_value() {
+    let site = Site::new("strict-sections");
+
+    site.write("templates/page.html", PAGE_TEMPLATE).write(
+        "htdocs/index.html.sssg",
+        r#"
+[config]
+template = "page.html"
+
+[plaintext]
+title = "Home"
+count = 5
+draft = true
+tags = ["a", "b"]
+
+[markdown]
+body = "hello"
+intro = 3
+"#,
+    );
+
+    let output = site.build(&["--strict-sections"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    for key in [
+        "plaintext.count",
+        "plaintext.draft",
+        "plaintext.tags",
+        "markdown.intro",
+    ] {
+        assert!(stderr.contains(&format!("'{}'", key)), "{}", stderr);
+    }
+    assert!(!stderr.contains("'plaintext.title'"), "{}", stderr);
+    assert!(!stderr.contains("'markdown.body'"), "{}", stderr);
+}
+
+#[test]
+fn inline_images_resolve_sources_under_the_base_path() {
+    let site = Site::new("inline-images-base-path");
+
+    site.write("sssg.toml", "[config]\nbase_path = \"/docs\"\n")
+        .write(
+            "templates/page.html",
+            "<html><body><img src=\"/docs/img.gif\"><img src=\"/docsimg.gif\"></body></html>",
+        )
+        .write("htdocs/img.gif", "GIF89a")
+        .write("htdocs/docsimg.gif", "GIF89a")
+        .write(
+            "htdocs/index.html.sssg",
+            "[config]\ntemplate = \"page.html\"\n",
+        );
+
+    site.build(&["--inline-images-below", "1024"]);
+    let html = site.read("htdocs/index.html");
+
+    assert_eq!(
+        html.matches("src=\"data:image/gif;base64,").count(),
+        2,
+        "{}",
+        html
+    );
+}
@@ -0,0 +1,221 @@
+#![allow(dead_code)]
+
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+pub struct Site {
+    pub root: PathBuf,
+}
+
+impl Site {
+    pub fn new(name: &str) -> Site {
+        let root = std::env::temp_dir().join(format!("sssg-test-{}-{}", std::process::id(), name));
+
+        let _ = remove_dir_all(&root);
+        create_dir_all(root.join("htdocs")).unwrap();
+        create_dir_all(root.join("templates")).unwrap();
+
+        Site { root }
+    }
+
+    pub fn write(&self, path: &str, contents: &str) -> &Site {
+        let path = self.root.join(path);
+
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, contents).unwrap();
+        self
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        read_to_string(self.root.join(path)).unwrap()
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.run_with_cache(&[&["--no-cache"], args].concat())
+    }
+
+    pub fn run_with_cache(&self, args: &[&str]) -> Output {
+        self.run_with_env(&[], args)
+    }
+
+    pub fn run_with_env(&self, env: &[(&str, &str)], args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_sssg"))
+            .arg("--build")
+            .args(["--minify-html", "none"])
+            .args(args)
+            .envs(env.iter().copied())
+            .current_dir(&self.root)
+            .output()
+            .unwrap()
+    }
+
+    pub fn clean(&self) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_sssg"))
+            .arg("--clean")
+            .current_dir(&self.root)
+            .output()
+            .unwrap();
+
+        assert_built(&output);
+        output
+    }
+
+    pub fn pipe(&self, args: &[&str], input: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sssg"))
+            .arg("--stdin")
+            .args(args)
+            .current_dir(&self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+
+        child.wait_with_output().unwrap()
+    }
+
+    pub fn build(&self, args: &[&str]) -> Output {
+        let output = self.run(args);
+        assert_built(&output);
+        output
+    }
+
+    pub fn build_with_cache(&self, args: &[&str]) -> Output {
+        let output = self.run_with_cache(args);
+        assert_built(&output);
+        output
+    }
+
+    pub fn serve(&self, args: &[&str]) -> Server {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_sssg"))
+            .arg("--serve")
+            .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+            .args(args)
+            .current_dir(&self.root)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+
+            sleep(Duration::from_millis(50));
+        }
+
+        Server { child, port }
+    }
+}
+
+fn assert_built(output: &Output) {
+    assert!(
+        output.status.success(),
+        "build failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+impl Drop for Site {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.root);
+    }
+}
+
+pub struct Server {
+    child: Child,
+    port: u16,
+}
+
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Server {
+    pub fn get(&self, path: &str) -> Reply {
+        self.request("GET", path, &[])
+    }
+
+    pub fn get_with(&self, path: &str, headers: &[(&str, &str)]) -> Reply {
+        self.request("GET", path, headers)
+    }
+
+    pub fn post(&self, path: &str) -> Reply {
+        self.request("POST", path, &[])
+    }
+
+    fn request(&self, method: &str, path: &str, headers: &[(&str, &str)]) -> Reply {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+
+        let extra: String = headers
+            .iter()
+            .map(|(n, v)| format!("{n}: {v}\r\n"))
+            .collect();
+
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: 127.0.0.1\r\n{extra}Content-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let mut lines = head.lines();
+
+        let status = lines
+            .next()
+            .and_then(|l| l.split(' ').nth(1))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+
+        let headers = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(n, v)| (n.trim().to_string(), v.trim().to_string()))
+            .collect();
+
+        Reply {
+            status,
+            headers,
+            body: body.to_string(),
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
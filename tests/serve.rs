@@ -0,0 +1,183 @@
+mod common;
+
+use common::Site;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[test]
+fn nested_directory_without_slash_redirects() {
+    let site = Site::new("nested-redirect");
+    site.write("htdocs/blog/posts/index.html", "<p>posts</p>");
+
+    let server = site.serve(&[]);
+    let reply = server.get("/blog/posts");
+
+    assert_eq!(reply.status, 301);
+    assert_eq!(reply.header("Location"), Some("/blog/posts/"));
+    assert_eq!(server.get("/blog/posts/").status, 200);
+}
+
+#[test]
+fn directory_redirect_keeps_query_string() {
+    let site = Site::new("query-redirect");
+    site.write("htdocs/blog/posts/index.html", "<p>posts</p>");
+
+    let server = site.serve(&[]);
+    let reply = server.get("/blog/posts?page=2");
+
+    assert_eq!(reply.status, 301);
+    assert_eq!(reply.header("Location"), Some("/blog/posts/?page=2"));
+}
+
+#[test]
+fn directory_redirect_encodes_non_ascii_paths() {
+    let site = Site::new("encoded-redirect");
+    site.write("htdocs/café/index.html", "<p>menu</p>");
+
+    let server = site.serve(&[]);
+    let reply = server.get("/caf%C3%A9");
+
+    assert_eq!(reply.status, 301);
+    assert_eq!(reply.header("Location"), Some("/caf%C3%A9/"));
+}
+
+#[test]
+fn rebuild_with_a_missing_template_reports_it_and_keeps_serving() {
+    let site = Site::new("missing-template-rebuild");
+
+    site.write("htdocs/static.html", "<p>static</p>").write(
+        "htdocs/index.html.sssg",
+        "[config]\ntemplate = \"missing.html\"\n",
+    );
+
+    let server = site.serve(&["--fail-on-missing-template"]);
+    let reply = server.post("/__sssg/rebuild");
+
+    assert_eq!(reply.status, 500);
+    assert!(reply.body.contains("Missing template"));
+    assert_eq!(server.get("/static.html").status, 200);
+}
+
+#[test]
+fn watch_mode_updates_tag_pages_when_a_tag_is_removed() {
+    let site = Site::new("watch-tags");
+
+    let post = |tags: &str| {
+        format!(
+            "[config]\ntemplate = \"post.html\"\ntags = [{tags}]\n\n[plaintext]\ntitle = \"Post\"\n"
+        )
+    };
+
+    site.write("sssg.toml", "[tags]\ntemplate = \"tag.html\"\n")
+        .write("templates/post.html", "<h1>{title}</h1>")
+        .write(
+            "templates/tag.html",
+            "<h1>{tag}</h1>{#each items}<p>{title}</p>{/each}",
+        )
+        .write("htdocs/a.html.sssg", &post("\"rust\", \"web\""))
+        .write("htdocs/b.html.sssg", &post("\"rust\""));
+
+    let _server = site.serve(&["--build", "--no-cache", "--watch", "--poll", "50"]);
+    let tags = site.root.join("htdocs/tags");
+
+    assert!(tags.join("web.html").exists());
+
+    for _ in 0..50 {
+        if !tags.join("web.html").exists() {
+            break;
+        }
+
+        site.write("htdocs/a.html.sssg", &post("\"rust\""));
+        sleep(Duration::from_millis(200));
+    }
+
+    assert!(!tags.join("web.html").exists());
+    assert_eq!(
+        site.read("htdocs/tags/rust.html")
+            .matches("<p>Post</p>")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn dotfiles_are_forbidden_by_default() {
+    let site = Site::new("dotfiles-forbidden");
+    site.write("htdocs/.git/config", "[core]\n");
+
+    let server = site.serve(&[]);
+
+    assert_eq!(server.get("/.git/config").status, 403);
+}
+
+#[test]
+fn allow_dotfiles_serves_well_known_paths() {
+    let site = Site::new("dotfiles-allowed");
+    site.write("htdocs/.well-known/acme-challenge/x", "token");
+
+    let server = site.serve(&["--allow-dotfiles"]);
+    let reply = server.get("/.well-known/acme-challenge/x");
+
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.body, "token");
+}
+
+#[test]
+fn rebuild_with_a_broken_site_config_reports_it_and_keeps_serving() {
+    let site = Site::new("broken-config-rebuild");
+    site.write("htdocs/static.html", "<p>static</p>");
+
+    let server = site.serve(&[]);
+    site.write("sssg.toml", "[tags\n");
+    let reply = server.post("/__sssg/rebuild");
+
+    assert_eq!(reply.status, 500);
+    assert!(reply.body.contains("TOML parse error in"), "{}", reply.body);
+    assert_eq!(server.get("/static.html").status, 200);
+}
+
+#[test]
+fn watch_mode_survives_a_broken_site_config() {
+    let site = Site::new("watch-broken-config");
+
+    let page = |title: &str| {
+        format!("[config]\ntemplate = \"page.html\"\n\n[plaintext]\ntitle = \"{title}\"\n")
+    };
+
+    site.write("templates/page.html", "<p>{title}</p>")
+        .write("htdocs/index.html.sssg", &page("First"));
+
+    let server = site.serve(&["--build", "--no-cache", "--watch", "--poll", "50"]);
+
+    site.write("sssg.toml", "[tags\n");
+    site.write("htdocs/index.html.sssg", &page("Broken"));
+    sleep(Duration::from_millis(500));
+    site.write("sssg.toml", "");
+
+    for _ in 0..50 {
+        if server.get("/index.html").body.contains("Second") {
+            break;
+        }
+
+        site.write("htdocs/index.html.sssg", &page("Second"));
+        sleep(Duration::from_millis(200));
+    }
+
+    assert!(server.get("/index.html").body.contains("Second"));
+}
+
+#[test]
+fn not_modified_replies_vary_by_encoding_like_full_replies() {
+    let site = Site::new("not-modified-vary");
+    site.write("htdocs/style.css", "body { color: black; }\n");
+
+    let server = site.serve(&["--min-compress-size", "1"]);
+    let full = server.get("/style.css");
+    let etag = full.header("ETag").unwrap().to_string();
+
+    let reply = server.get_with("/style.css", &[("If-None-Match", &etag)]);
+
+    assert_eq!(full.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(reply.status, 304);
+    assert_eq!(reply.header("Vary"), Some("Accept-Encoding"));
+}